@@ -0,0 +1,7 @@
+use aoc2019::intcode::dap;
+use std::io;
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    dap::serve(stdin.lock(), io::stdout())
+}
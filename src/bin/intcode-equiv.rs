@@ -0,0 +1,48 @@
+use aoc2019::intcode::Computer;
+use aoc2019::intcode::equiv::Checker;
+use aoc2019::intcode::fuzz::Limits;
+use std::{env, fs, panic, process};
+
+/// intcode-equiv <a> <b> [steps] [input...]
+///
+/// Each input is a comma separated sequence, e.g. `1,2,3`, tried before the generated ones.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("usage: intcode-equiv <a> <b> [steps] [input...]");
+        process::exit(2);
+    }
+    let load = |path: &String| {
+        let src = fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(2);
+        });
+        Computer::load(&src).unwrap_or_else(|err| {
+            eprintln!("{}:{}", path, err);
+            process::exit(2);
+        })
+    };
+    let (a, b) = (load(&args[0]), load(&args[1]));
+    let steps = args.get(2).and_then(|n| n.parse().ok()).unwrap_or(100_000);
+
+    let mut checker = Checker::new(&a, &b).limits(Limits { steps, mem: 1 << 20 });
+    for arg in args.iter().skip(3) {
+        let input = arg.split(',').filter(|n| !n.trim().is_empty()).map(|n| n.trim().parse()).collect();
+        match input {
+            Ok(input) => checker = checker.input(input),
+            Err(_) => {
+                eprintln!("`{}` is not a comma separated list of numbers", arg);
+                process::exit(2);
+            }
+        }
+    }
+    // a crash is part of how a program behaves, it's compared and shown in the mismatch instead
+    panic::set_hook(Box::new(|_| {}));
+    match checker.check() {
+        Some(mismatch) => {
+            print!("{}", mismatch);
+            process::exit(1);
+        }
+        None => println!("no difference found in {} runs", checker.runs()),
+    }
+}
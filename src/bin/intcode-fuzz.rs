@@ -0,0 +1,38 @@
+use aoc2019::intcode::Computer;
+use aoc2019::intcode::fuzz::Fuzzer;
+use std::{env, fs, panic, process};
+
+/// intcode-fuzz <program> [iterations] [seed input...]
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = args.first().unwrap_or_else(|| {
+        eprintln!("usage: intcode-fuzz <program> [iterations] [seed input...]");
+        process::exit(2);
+    });
+    let src = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    let image = Computer::load(&src).unwrap_or_else(|err| {
+        eprintln!("{}:{}", path, err);
+        process::exit(1);
+    });
+    let iterations = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(10_000);
+    let seed: Vec<i64> = args.iter().skip(2).filter_map(|n| n.parse().ok()).collect();
+
+    // every crash the fuzzer finds is caught and listed below, the default hook would print each one
+    panic::set_hook(Box::new(|_| {}));
+    let mut fuzzer = Fuzzer::new(&image).seed(seed);
+    fuzzer.fuzz(iterations);
+
+    println!("{} runs, {} addresses covered, {} inputs in the corpus", fuzzer.runs(), fuzzer.covered().len(), fuzzer.corpus().len());
+    for input in fuzzer.corpus() {
+        println!("  {:?}", input);
+    }
+    for (input, crash, stack) in fuzzer.crashes() {
+        println!("{}\n  {:?}", crash, input);
+        for line in stack.to_string().lines() {
+            println!("  {}", line);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use aoc2019::intcode::Computer;
+use aoc2019::intcode::fuzz::Limits;
+use std::{env, fs, panic, process};
+
+const MAX_STEPS: u64 = 10_000_000;
+
+/// intcode-heatmap <program> <out.ppm|out.png> [--width=N] [addr=val...] [input...]
+///
+/// `addr=val` patches memory before the run, e.g. `0=2` to put quarters in day 13's machine. The input
+/// is given again from the start whenever the program has used it all up, so interactive programs keep
+/// running until they halt, crash or hit the step limit. The heatmap is written either way.
+fn main() {
+    let (flags, args): (Vec<String>, Vec<String>) = env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let mut width = 64;
+    for flag in &flags {
+        match flag.strip_prefix("--width=").map(str::parse) {
+            Some(Ok(n)) if n > 0 => width = n,
+            _ => {
+                eprintln!("unknown flag {}", flag);
+                process::exit(2);
+            }
+        }
+    }
+    if args.len() < 2 {
+        eprintln!("usage: intcode-heatmap <program> <out.ppm|out.png> [--width=N] [addr=val...] [input...]");
+        process::exit(2);
+    }
+    let (path, out) = (&args[0], &args[1]);
+    let src = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    let mut image = Computer::load(&src).unwrap_or_else(|err| {
+        eprintln!("{}:{}", path, err);
+        process::exit(1);
+    });
+    for (addr, val) in args.iter().skip(2).filter_map(|arg| arg.split_once('=')) {
+        if let (Ok(addr), Ok(val)) = (addr.parse::<usize>(), val.parse()) {
+            if addr >= image.len() {
+                image.resize(addr + 1, 0);
+            }
+            image[addr] = val;
+        }
+    }
+    let input: Vec<i64> = args.iter().skip(2).filter_map(|n| n.parse().ok()).collect();
+
+    let mut com = Computer::init(&image, input.iter().copied());
+    com.track_usage();
+    com.track_calls();
+    let limits = Limits { steps: MAX_STEPS, ..Limits::default() };
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut from = 0;
+    let crash = loop {
+        let ptr = com.ptr();
+        match com.try_step(from, limits) {
+            Ok(true) => from = ptr,
+            Ok(false) => {
+                com.recv_all().for_each(drop);
+                if com.is_done || input.is_empty() {
+                    break None;
+                }
+                com.send_all(input.iter().copied());
+            }
+            Err(crash) => break Some(crash),
+        }
+    };
+    panic::set_hook(hook);
+
+    let usage = com.stop_usage().unwrap();
+    let heatmap = usage.heatmap(width, 4);
+    let bytes = if out.ends_with(".png") { heatmap.png() } else { heatmap.ppm() };
+    fs::write(out, bytes).unwrap_or_else(|err| {
+        eprintln!("{}: {}", out, err);
+        process::exit(1);
+    });
+    if let Some(crash) = crash {
+        eprintln!("stopped: {}", crash);
+    }
+    let count = |counts: &[u64]| counts.iter().filter(|&&n| n > 0).count();
+    println!("{} steps, {} addresses executed, {} read, {} written", com.steps(), count(&usage.exec), count(&usage.read), count(&usage.write));
+    for (func, n) in usage.busiest().into_iter().take(10) {
+        println!("  {:>10} steps in the function at {:#05x}", n, func);
+    }
+}
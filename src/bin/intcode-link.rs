@@ -0,0 +1,42 @@
+use aoc2019::intcode::Computer;
+use aoc2019::intcode::link::{link, Module};
+use std::path::Path;
+use std::{env, fs, process};
+
+/// intcode-link <out> <module...>
+///
+/// Writes the linked program to `out`, its symbol map to `out.map` and a listing the debug adapter can
+/// step through to `out.lst`. The first module holds the entry point.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("usage: intcode-link <out> <module...>");
+        process::exit(2);
+    }
+    let modules: Vec<Module> = args[1..].iter()
+        .map(|path| {
+            let src = fs::read_to_string(path).unwrap_or_else(|err| {
+                eprintln!("{}: {}", path, err);
+                process::exit(1);
+            });
+            let name = Path::new(path).file_stem().map_or(path.as_str(), |stem| stem.to_str().unwrap_or(path));
+            Module::assemble(name, &src).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            })
+        })
+        .collect();
+    let linked = link(&modules).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let out = &args[0];
+    for (path, text) in [(out.clone(), Computer::to_text(&linked.image)), (format!("{}.map", out), linked.symbols.to_string()), (format!("{}.lst", out), linked.listing)] {
+        fs::write(&path, text).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        });
+    }
+    println!("{} words from {} modules, {} symbols", linked.image.len(), modules.len(), linked.symbols.symbols.len());
+}
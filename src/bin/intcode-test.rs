@@ -0,0 +1,39 @@
+use aoc2019::intcode::testcase::Case;
+use std::path::Path;
+use std::{env, process};
+
+/// intcode-test [dir or file...], runs the cases in `intcode-tests` by default
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        args.push("intcode-tests".to_string());
+    }
+    let mut cases = Vec::new();
+    for arg in &args {
+        let path = Path::new(arg);
+        let loaded = if path.is_dir() { Case::load_dir(path) } else { Case::load(path) };
+        match loaded {
+            Ok(loaded) => cases.extend(loaded),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(2);
+            }
+        }
+    }
+
+    let mut failed = 0;
+    for case in &cases {
+        match case.run() {
+            Ok(()) => println!("ok      {}", case.name),
+            Err(failure) => {
+                println!("FAILED  {}", case.name);
+                eprint!("{}", failure);
+                failed += 1;
+            }
+        }
+    }
+    println!("{} passed, {} failed", cases.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::intcode::Computer;
+use crate::intcode::loader::ParseError;
 use std::iter::empty;
 use std::collections::HashMap;
 use std::ops::{AddAssign, Add};
@@ -7,8 +8,8 @@ use std::cmp::{min, max};
 use std::hint::unreachable_unchecked;
 
 #[aoc_generator(day11)]
-fn gen(input: &str) -> Vec<i64> {
-    Computer::parse_mem(input)
+fn gen(input: &str) -> Result<Vec<i64>, ParseError> {
+    Computer::load(input)
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Default)]
@@ -92,7 +93,7 @@ impl Robot {
 }
 
 #[aoc(day11, part1)]
-fn part1(mem: &Vec<i64>) -> usize {
+fn part1(mem: &[i64]) -> usize {
     let mut com = Computer::init(mem, empty());
 
     let mut panels = HashMap::<Pt, bool>::new();
@@ -124,7 +125,7 @@ fn part1(mem: &Vec<i64>) -> usize {
 }
 
 #[aoc(day11, part2)]
-fn part2(mem: &Vec<i64>) -> String {
+fn part2(mem: &[i64]) -> String {
     let mut com = Computer::init(mem, empty());
 
     let mut panels = HashMap::<Pt, bool>::new();
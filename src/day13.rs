@@ -1,69 +1,18 @@
-use Id::*;
+mod arcade;
+
+use arcade::Arcade;
 use crate::intcode::Computer;
+use crate::intcode::loader::ParseError;
+use crate::intcode::threaded::Threaded;
 use std::iter::empty;
-use itertools::{Itertools, Chunk};
-use std::collections::HashSet;
-use std::hint::unreachable_unchecked;
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum Id {
-    Empty,
-    Wall,
-    Block,
-    Paddle,
-    Ball,
-    Score(i64),
-}
-
-impl<N: Into<i64>> From<N> for Id {
-    fn from(n: N) -> Self {
-        match n.into() {
-            0 => Empty,
-            1 => Wall,
-            2 => Block,
-            3 => Paddle,
-            4 => Ball,
-            _ => unsafe { unreachable_unchecked() },
-//            _ => unreachable!(),
-        }
-    }
-}
-
-#[derive(Eq, PartialEq, Copy, Clone, Debug)]
-struct Tile {
-    x: i64,
-    y: i64,
-    id: Id,
-}
-
-impl<I> From<Chunk<'_, I>> for Tile
-    where I: Itertools<Item=i64> {
-    fn from(chunk: Chunk<I>) -> Self {
-        let vec: Vec<i64> = chunk.collect();
-        let x = vec[0];
-        let y = vec[1];
-        match (x, y) {
-            (-1, 0) => Tile {
-                x,
-                y,
-                id: Score(vec[2]),
-            },
-            (_x, _) => Tile {
-                x,
-                y,
-                id: vec[2].into(),
-            }
-        }
-    }
-}
 
 #[aoc_generator(day13)]
-fn gen(input: &str) -> Vec<i64> {
-    Computer::parse_mem(input)
+fn gen(input: &str) -> Result<Vec<i64>, ParseError> {
+    Computer::load(input)
 }
 
 //#[aoc(day13, part1)]
-//fn part1(mem: &Vec<i64>) -> i64 {
+//fn part1(mem: &[i64]) -> i64 {
 //    let mut com = Computer::init(mem, empty());
 //    com.compute();
 //    let borrow_checker = com.recv_all()
@@ -75,39 +24,28 @@ fn gen(input: &str) -> Vec<i64> {
 //}
 
 #[aoc(day13, part2)]
-fn part2(mem: &Vec<i64>) -> i64 {
-    let mut com = Computer::init(mem, empty());
+fn part2(mem: &[i64]) -> i64 {
+    play(mem, |com| com.compute())
+}
 
-    com.mem[0] = 2;
+#[aoc(day13, part2, Threaded)]
+fn part2_threaded(mem: &[i64]) -> i64 {
+    let mut code = Threaded::new();
+    play(mem, |com| code.run(com))
+}
 
-    let mut paddle_x = 0;
-    let mut ball_x = 0;
-    let mut blocks = HashSet::new();
-    let mut score = 0;
+fn play<F: FnMut(&mut Computer)>(mem: &[i64], mut run: F) -> i64 {
+    let mut com = Computer::init(mem, empty());
+    let arcade = Arcade::new(&mut com);
 
     loop {
-        com.compute();
-
-        com.recv_all()
-            .chunks(3)
-            .into_iter()
-            .map(|chunk| Tile::from(chunk))
-            .for_each(|Tile { x, y, id }| match id {
-                Block => { blocks.insert((x, y)); }
-                Paddle => paddle_x = x,
-                Ball => ball_x = x,
-                Score(sc) => {
-                    println!("{}", sc - score);
-                    score = sc
-                }
-                Empty => { blocks.remove(&(x, y)); }
-                _ => {}
-            });
+        run(&mut com);
+        // the screen has seen every tile already
+        com.recv_all().for_each(drop);
+        com.send(arcade.joystick());
 
-        com.send((ball_x - paddle_x).signum());
-
-        if blocks.is_empty() {
-            break score;
+        if arcade.cleared() {
+            break arcade.score();
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,118 @@
+use Id::*;
+use crate::intcode::Computer;
+use crate::intcode::observe::Record;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::hint::unreachable_unchecked;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Id {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+    Score(i64),
+}
+
+impl<N: Into<i64>> From<N> for Id {
+    fn from(n: N) -> Self {
+        match n.into() {
+            0 => Empty,
+            1 => Wall,
+            2 => Block,
+            3 => Paddle,
+            4 => Ball,
+            _ => unsafe { unreachable_unchecked() },
+//            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Tile {
+    pub x: i64,
+    pub y: i64,
+    pub id: Id,
+}
+
+impl Record for Tile {
+    const ARITY: usize = 3;
+
+    fn decode(vals: &[i64]) -> Self {
+        let x = vals[0];
+        let y = vals[1];
+        match (x, y) {
+            (-1, 0) => Tile {
+                x,
+                y,
+                id: Score(vals[2]),
+            },
+            (_x, _) => Tile {
+                x,
+                y,
+                id: vals[2].into(),
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Screen {
+    paddle_x: i64,
+    ball_x: i64,
+    blocks: HashSet<(i64, i64)>,
+    score: i64,
+    // everything drawn since the joystick last moved
+    drawn: Vec<Tile>,
+}
+
+impl Screen {
+    fn draw(&mut self, tile: Tile) {
+        self.drawn.push(tile);
+        let Tile { x, y, id } = tile;
+        match id {
+            Block => { self.blocks.insert((x, y)); }
+            Paddle => self.paddle_x = x,
+            Ball => self.ball_x = x,
+            Score(sc) => {
+                println!("{:?}", self.drawn);
+                println!("{}", sc - self.score);
+                self.score = sc
+            }
+            Empty => { self.blocks.remove(&(x, y)); }
+            _ => {}
+        }
+    }
+}
+
+/// The cabinet around the game: it watches every tile the program draws and works the joystick
+pub struct Arcade {
+    screen: Arc<Mutex<Screen>>,
+}
+
+impl Arcade {
+    /// Puts in the quarters and hooks the screen up to `com`'s output
+    pub fn new(com: &mut Computer) -> Self {
+        com.mem[0] = 2;
+        let screen = Arc::new(Mutex::new(Screen::default()));
+        let hook = screen.clone();
+        com.observe_records(move |tile: Tile| hook.lock().unwrap().draw(tile));
+        Arcade { screen }
+    }
+
+    /// Follows the ball with the paddle
+    pub fn joystick(&self) -> i64 {
+        let mut screen = self.screen.lock().unwrap();
+        screen.drawn.clear();
+        (screen.ball_x - screen.paddle_x).signum()
+    }
+
+    pub fn cleared(&self) -> bool {
+        self.screen.lock().unwrap().blocks.is_empty()
+    }
+
+    pub fn score(&self) -> i64 {
+        self.screen.lock().unwrap().score
+    }
+}
@@ -1,10 +1,11 @@
 use crate::intcode::Computer;
+use crate::intcode::loader::ParseError;
 use std::io::empty;
 use std::collections::HashMap;
 
 #[aoc_generator(day15)]
-fn gen(input: &str) -> Vec<i64> {
-    Computer::parse_mem(input)
+fn gen(input: &str) -> Result<Vec<i64>, ParseError> {
+    Computer::load(input)
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -1,36 +1,65 @@
-use std::hint::unreachable_unchecked;
+use crate::intcode::Computer;
+use crate::intcode::batch::Batch;
+use crate::intcode::loader::ParseError;
+use crate::intcode::lockstep::Lockstep;
+use crate::intcode::symbolic::solve;
+use std::thread;
 
 #[aoc_generator(day2)]
-fn gen(input: &str) -> Vec<i32> {
-    input.split(",")
-        .map(|n| n.parse().unwrap())
-        .collect()
+fn gen(input: &str) -> Result<Vec<i64>, ParseError> {
+    Computer::load(input)
 }
 
 #[aoc(day2, part1)]
-fn part1(input: &Vec<i32>) -> i32 {
-    let mut mem = input.clone();
+fn part1(input: &[i64]) -> i64 {
+    let mut mem = input.to_vec();
     mem[1] = 12;
     mem[2] = 2;
     compute(&mut mem)
 }
 
 #[aoc(day2, part2)]
-fn part2(input: &Vec<i32>) -> i32 {
-    for noun in 0..=99 {
-        for verb in 0..=99 {
-            let mut mem = input.clone();
-            mem[1] = noun;
-            mem[2] = verb;
-            if compute(&mut mem) == 19690720 {
-                return 100 * noun + verb;
-            }
-        }
-    }
-    unsafe { unreachable_unchecked() }
+fn part2(mem: &[i64]) -> Option<i64> {
+    let found = solve(mem, &[(1, 0..=99), (2, 0..=99)], 0, 19690720);
+    let (noun, verb) = match found.solutions.first()?.as_slice() {
+        &[noun, verb] => (noun, verb),
+        _ => return None,
+    };
+    Some(100 * noun + verb)
+}
+
+/// Tries every noun and verb on the real program, spread over all cores
+#[aoc(day2, part2, Batch)]
+fn part2_batch(mem: &[i64]) -> Option<i64> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let jobs = (0..100).flat_map(|noun| (0..100).map(move |verb| (vec![(1, noun), (2, verb)], vec![])));
+    // nouns go in the outer loop, so the job index is already 100 * noun + verb
+    let (i, ()) = Batch::new(mem, threads).find(jobs, |com| (com.mem[0] == 19690720).then_some(()))?;
+    Some(i as i64)
+}
+
+/// Tries every noun and verb on the real program one after another, each on its own `Computer`
+#[aoc(day2, part2, Separate)]
+fn part2_separate(mem: &[i64]) -> Option<i64> {
+    let (noun, verb) = (0..100).flat_map(|noun| (0..100).map(move |verb| (noun, verb))).find(|&(noun, verb)| {
+        let mut com = Computer::init(mem, vec![]);
+        com.mem[1] = noun;
+        com.mem[2] = verb;
+        com.compute();
+        com.mem[0] == 19690720
+    })?;
+    Some(100 * noun + verb)
+}
+
+/// Tries every noun and verb on the real program, all of them stepped together
+#[aoc(day2, part2, Lockstep)]
+fn part2_lockstep(mem: &[i64]) -> Option<i64> {
+    let jobs: Vec<_> = (0..100).flat_map(|noun| (0..100).map(move |verb| (vec![(1, noun), (2, verb)], vec![]))).collect();
+    let i = Lockstep::new(mem).run(&jobs).iter().position(|outcome| outcome.mem[0] == 19690720)?;
+    Some(i as i64)
 }
 
-fn compute(mem: &mut Vec<i32>) -> i32 {
+fn compute(mem: &mut [i64]) -> i64 {
     let mut ptr = 0;
     loop {
         match mem[ptr] {
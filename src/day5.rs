@@ -1,13 +1,14 @@
 use crate::intcode::Computer;
+use crate::intcode::loader::ParseError;
 use std::iter::once;
 
 #[aoc_generator(day5)]
-fn gen(input: &str) -> Vec<i64> {
-    Computer::parse_mem(input)
+fn gen(input: &str) -> Result<Vec<i64>, ParseError> {
+    Computer::load(input)
 }
 
 #[aoc(day5, part1)]
-fn part1(mem: &Vec<i64>) -> String {
+fn part1(mem: &[i64]) -> String {
     let mut com = Computer::init(mem, once(1));
 
     com.compute();
@@ -18,7 +19,7 @@ fn part1(mem: &Vec<i64>) -> String {
 }
 
 #[aoc(day5, part2)]
-fn part2(mem: &Vec<i64>) ->  String {
+fn part2(mem: &[i64]) ->  String {
     let mut com = Computer::init(mem, once(5));
     com.compute();
     com.recv_all()
@@ -1,16 +1,19 @@
 use crate::intcode::Computer;
+use crate::intcode::loader::ParseError;
+use crate::intcode::batch::{Batch, Job};
 use std::cmp::max;
 use std::collections::HashSet;
 use std::iter::once;
 use std::ops::Range;
+use std::thread;
 
 #[aoc_generator(day7)]
-fn gen(input: &str) -> Vec<i64> {
-    Computer::parse_mem(input)
+fn gen(input: &str) -> Result<Vec<i64>, ParseError> {
+    Computer::load(input)
 }
 
 #[aoc(day7, part1)]
-fn part1(mem: &Vec<i64>) -> i64 {
+fn part1(mem: &[i64]) -> i64 {
     let mut max_sig = 0;
 
     iter(0_i64..5, |a, b, c, d, e| {
@@ -46,8 +49,28 @@ fn part1(mem: &Vec<i64>) -> i64 {
     max_sig
 }
 
+/// Runs every phase setting through one amplifier at a time, all settings at once
+#[aoc(day7, part1, Batch)]
+fn part1_batch(mem: &[i64]) -> i64 {
+    let mut phases = Vec::new();
+    iter(0_i64..5, |a, b, c, d, e| phases.push([a, b, c, d, e]));
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let batch = Batch::new(mem, threads);
+    let mut sigs = vec![0; phases.len()];
+    for amp in 0..5 {
+        let jobs: Vec<Job> = phases.iter()
+            .zip(&sigs)
+            .map(|(phase, &sig)| (vec![], vec![phase[amp], sig]))
+            .collect();
+        sigs = batch.run(jobs, |com| com.recv().unwrap());
+    }
+
+    sigs.into_iter().max().unwrap_or(0)
+}
+
 #[aoc(day7, part2)]
-fn part2(mem: &Vec<i64>) -> i64 {
+fn part2(mem: &[i64]) -> i64 {
     let mut max_sig = 0;
 
     iter(5_i64..10, |a, b, c, d, e| {
@@ -78,13 +101,13 @@ fn part2(mem: &Vec<i64>) -> i64 {
     max_sig
 }
 
-fn iter<F>(range: Range<i64>, mut f: F) where F: FnMut(i64, i64, i64, i64, i64) -> () {
+fn iter<F>(range: Range<i64>, mut f: F) where F: FnMut(i64, i64, i64, i64, i64) {
     for a in range.clone() {
         for b in range.clone() {
             for c in range.clone() {
                 for d in range.clone() {
                     for e in range.clone() {
-                        if vec![a, b, c, d, e].iter().collect::<HashSet<_>>().len() != 5 {
+                        if [a, b, c, d, e].iter().collect::<HashSet<_>>().len() != 5 {
                             continue;
                         }
                         f(a, b, c, d, e);
@@ -1,13 +1,15 @@
 use crate::intcode::Computer;
+use crate::intcode::loader::ParseError;
+use crate::intcode::threaded::Threaded;
 use std::iter::once;
 
 #[aoc_generator(day9)]
-fn gen(input: &str) -> Vec<i64> {
-    Computer::parse_mem(input)
+fn gen(input: &str) -> Result<Vec<i64>, ParseError> {
+    Computer::load(input)
 }
 
 #[aoc(day9, part1)]
-fn part1(mem: &Vec<i64>) -> String {
+fn part1(mem: &[i64]) -> String {
     let mut com = Computer::init(mem, once(1));
     com.compute();
     com.recv_all()
@@ -16,10 +18,19 @@ fn part1(mem: &Vec<i64>) -> String {
 }
 
 #[aoc(day9, part2)]
-fn part2(mem: &Vec<i64>) -> String {
+fn part2(mem: &[i64]) -> String {
     let mut com = Computer::init(mem, once(2));
     com.compute();
     com.recv_all()
         .map(|n| format!("{}, ", n))
         .collect()
+}
+
+#[aoc(day9, part2, Threaded)]
+fn part2_threaded(mem: &[i64]) -> String {
+    let mut com = Computer::init(mem, once(2));
+    Threaded::new().run(&mut com);
+    com.recv_all()
+        .map(|n| format!("{}, ", n))
+        .collect()
 }
\ No newline at end of file
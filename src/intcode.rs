@@ -2,8 +2,47 @@ use Opcode::*;
 use Mode::*;
 use std::fmt::{Debug, Formatter, Error, Display};
 use std::io::Write;
-use std::collections::VecDeque;
+use std::collections::{VecDeque, HashMap};
+use std::sync::Arc;
 use std::hint::unreachable_unchecked;
+use history::History;
+use mmio::Region;
+use ext::Extension;
+use observe::Observers;
+use replay::{Session, Event};
+use calls::CallStack;
+use strict::Strict;
+use taint::Taint;
+use reset::Pristine;
+use heatmap::Usage;
+
+pub mod history;
+pub mod mmio;
+pub mod ext;
+pub mod loader;
+pub mod symbolic;
+pub mod decode;
+pub mod optimize;
+pub mod threaded;
+pub mod transpile;
+pub mod batch;
+pub mod observe;
+pub mod replay;
+pub mod json;
+pub mod dap;
+pub mod scan;
+pub mod calls;
+pub mod compile;
+pub mod strict;
+pub mod taint;
+pub mod fuzz;
+pub mod testcase;
+pub mod state;
+pub mod lockstep;
+pub mod reset;
+pub mod heatmap;
+pub mod link;
+pub mod equiv;
 
 //const DBG: bool = true;
 const DBG: bool = false;
@@ -16,50 +55,142 @@ pub struct Computer {
     output: VecDeque<i64>,
     rel_base: i64,
     pub is_done: bool,
+    history: Option<History>,
+    regions: Vec<Region>,
+    extensions: HashMap<i64, Arc<Extension>>,
+    observers: Observers,
+    steps: u64,
+    session: Option<Session>,
+    calls: Option<CallStack>,
+    strict: Option<Strict>,
+    taint: Option<Taint>,
+    fingerprint: Option<u64>,
+    pristine: Option<Pristine>,
+    usage: Option<Usage>,
 }
 
 impl Computer {
     pub fn compute(&mut self) {
-        loop {
-            if DBG { print!("{}: {} ", self.ptr, self.mem[self.ptr]); }
-            let orig_ptr = self.ptr;
-            let opcode = Opcode::from(&self);
-            if DBG { print!(" {} ", opcode); }
+        while self.step() {}
+    }
 
-            if let Err(code) = Opcode::calculate(&opcode, self) {
-                match code {
-                    Halt => {
-                        if DBG { println!() }
-                        self.is_done = true;
-                        return;
+    pub fn step(&mut self) -> bool {
+        if DBG { print!("{}: {} ", self.ptr, self.mem[self.ptr]); }
+        let orig_ptr = self.ptr;
+        let opcode = Opcode::from(self);
+        if DBG { print!(" {} ", opcode); }
+        if self.strict.is_some() && !self.check_reads(&opcode) {
+            return false;
+        }
+        if let Some(history) = &mut self.history {
+            history.begin(self.ptr, self.rel_base, self.steps, &self.mem, &self.input, self.calls.as_ref());
+        }
+        if let Ext(code) = opcode {
+            return self.step_ext(code);
+        }
+
+        if let Err(code) = Opcode::calculate(&opcode, self) {
+            match code {
+                Halt => {
+                    if DBG { println!() }
+                    let func = self.current_function();
+                    if let Some(usage) = &mut self.usage {
+                        usage.executed(orig_ptr, 1, func);
+                    }
+                    if let Some(session) = &mut self.session {
+                        session.push(Event::Halt { step: self.steps });
+                    }
+                    self.steps += 1;
+                    if let Some(history) = &mut self.history {
+                        history.commit();
+                    }
+                    self.is_done = true;
+                    return false;
+                }
+                JumpNZero(_, _) | JumpZero(_, _) => {}
+                Input(_) => {
+                    if let Some(history) = &mut self.history {
+                        history.cancel();
                     }
-                    JumpNZero(_, _) | JumpZero(_, _) => {}
-                    Input(_) => return,
-//                    _ => unreachable!("{:?} should not error in calculation", opcode)
-                    _ => unsafe { unreachable_unchecked() },
+                    return false;
                 }
+//                _ => unreachable!("{:?} should not error in calculation", opcode)
+                _ => unsafe { unreachable_unchecked() },
             }
+        }
 
-            if orig_ptr == self.ptr {
-                self.ptr += opcode.nparams() + opcode.nwrites() + 1;
-            }
+        let func = self.current_function();
+        if let Some(usage) = &mut self.usage {
+            usage.executed(orig_ptr, opcode.nparams() + opcode.nwrites() + 1, func);
+        }
+        if self.taint.is_some() {
+            self.propagate(&opcode, orig_ptr);
+        }
+        if orig_ptr == self.ptr {
+            self.ptr += opcode.nparams() + opcode.nwrites() + 1;
+        } else if let Some(calls) = &mut self.calls {
+            calls.transfer(orig_ptr, self.ptr, orig_ptr + 3, self.rel_base);
+        }
+        self.steps += 1;
+        if let Some(history) = &mut self.history {
+            history.commit();
+        }
 
-            if DBG { println!(); }
-        };
+        if DBG { println!(); }
+        true
     }
 
-    fn read(&self, mode: &Mode) -> i64 {
-        *self.mem.get(mode.index()).unwrap_or(&0)
+    fn read(&mut self, mode: &Mode) -> i64 {
+        let index = mode.index();
+        let val = *self.mem.get(index).unwrap_or(&0);
+        if let (Some(usage), Pos(_) | Rel(_)) = (&mut self.usage, mode) {
+            usage.read(index);
+        }
+        if self.regions.is_empty() {
+            val
+        } else {
+            self.read_mapped(index, val)
+        }
     }
 
     fn write(&mut self, mode: &Mode, val: i64) {
         let index = mode.index();
+        let val = if self.regions.is_empty() {
+            val
+        } else {
+            match self.write_mapped(index, val) {
+                Some(val) => val,
+                None => return,
+            }
+        };
         if index >= self.mem.len() {
             self.mem.resize(index + 1, 0);
         }
+        if let Some(history) = &mut self.history {
+            history.write(index, self.mem[index], val);
+        }
+        if let Some(calls) = &mut self.calls {
+            calls.store(val);
+        }
+        if let Some(strict) = &mut self.strict {
+            strict.written(index);
+        }
+        if let Some(usage) = &mut self.usage {
+            usage.written(index);
+        }
+        state::rewrite(&mut self.fingerprint, index, self.mem[index], val);
+        reset::touch(&mut self.pristine, index);
         self.mem[index] = val
     }
 
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn ptr(&self) -> usize {
+        self.ptr
+    }
+
     pub fn send(&mut self, val: i64) {
         self.input.push_back(val);
     }
@@ -76,23 +207,31 @@ impl Computer {
         self.output.drain(..)
     }
 
-    pub fn init<I: IntoIterator<Item=i64>>(mem: &Vec<i64>, vals: I) -> Self {
+    pub fn init<I: IntoIterator<Item=i64>>(mem: &[i64], vals: I) -> Self {
         Computer {
-            mem: mem.clone(),
+            mem: mem.to_vec(),
             ptr: 0,
             input: vals.into_iter().collect(),
             output: Default::default(),
             rel_base: 0,
             is_done: false,
+            history: None,
+            regions: Vec::new(),
+            extensions: HashMap::new(),
+            observers: Observers::default(),
+            steps: 0,
+            session: None,
+            calls: None,
+            strict: None,
+            taint: None,
+            fingerprint: None,
+            pristine: None,
+            usage: None,
         }
     }
 
     pub fn parse_mem(mem: &str) -> Vec<i64> {
-        mem.lines()
-            .take(1)
-            .flat_map(|line| line.split(","))
-            .map(|n| n.trim().parse().unwrap())
-            .collect()
+        Computer::load(mem).unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
@@ -108,6 +247,7 @@ enum Opcode {
     Equal(Mode, Mode, Mode),
     SetRelBase(Mode),
     Halt,
+    Ext(i64),
 }
 
 impl Opcode {
@@ -131,6 +271,12 @@ impl Opcode {
                     Some(inp) => inp,
                     None => return Err(Input(Mode::dummy()))
                 };
+                if let Some(history) = &mut com.history {
+                    history.input(res);
+                }
+                if let Some(session) = &mut com.session {
+                    session.push(Event::Input { step: com.steps, val: res });
+                }
                 if DBG { print!("in={} @{}", res, w.index()); }
                 com.write(w, res);
                 Ok(())
@@ -139,10 +285,14 @@ impl Opcode {
                 let res = com.read(a);
                 if DBG { print!("out={}", res); }
                 com.output.push_back(res);
-                if com.output.len() % 3 == 0 && !com.output.is_empty() {
-                    if com.output[com.output.len() - 3] == -1 {
-                        println!("{:?}", com.output);
-                    }
+                if let Some(history) = &mut com.history {
+                    history.output(res);
+                }
+                if let Some(session) = &mut com.session {
+                    session.push(Event::Output { step: com.steps, val: res });
+                }
+                if !com.observers.is_empty() {
+                    com.observers.notify(res);
                 }
                 Ok(())
             }
@@ -187,6 +337,7 @@ impl Opcode {
                 Ok(())
             }
             Halt => Err(Halt),
+            Ext(_) => unsafe { unreachable_unchecked() }, // run by Computer::step_ext
         }
     }
 
@@ -202,6 +353,7 @@ impl Opcode {
             Equal(_, _, _) => 2,
             SetRelBase(_) => 1,
             Halt => 0,
+            Ext(_) => 0, // looked up in the extension table
         }
     }
 
@@ -217,6 +369,7 @@ impl Opcode {
             Equal(_, _, _) => 1,
             SetRelBase(_) => 0,
             Halt => 0,
+            Ext(_) => 0,
         }
     }
 
@@ -263,6 +416,7 @@ impl Opcode {
                 SetRelBase(Mode::from(com, 1))
             }
             99 => Halt,
+            _ if com.extensions.contains_key(&code) => Ext(code),
             _ => unsafe { unreachable_unchecked() },
 //            _ => unreachable!("Opcode::from instr={}", instr),
         }
@@ -282,6 +436,7 @@ impl Display for Opcode {
             Equal(_, _, _) => "Equal",
             SetRelBase(_) => "SetRelBase",
             Halt => "Halt",
+            Ext(_) => "Ext",
         })
     }
 }
@@ -295,7 +450,7 @@ enum Mode {
 
 impl Mode {
     fn from(computer: &Computer, offset: usize) -> Self {
-        let Computer { mem, ptr, input: _, output: _, rel_base, is_done: _ } = computer;
+        let Computer { mem, ptr, rel_base, .. } = computer;
         let ptr = *ptr;
         let rel_base = *rel_base;
         let instr = mem[ptr];
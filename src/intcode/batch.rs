@@ -0,0 +1,214 @@
+use crate::intcode::Computer;
+use std::any::Any;
+use std::iter::Enumerate;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Memory patches to apply to the image, then the input to run with
+pub type Job = (Vec<(usize, i64)>, Vec<i64>);
+
+type Task = Box<dyn FnOnce(&mut Computer) + Send>;
+
+/// Runs many independent jobs over one program image on a pool of threads. The threads are started
+/// once and live as long as the `Batch`, each keeping a single `Computer` that's reset to the image
+/// between jobs instead of allocating a new one, across any number of `run` and `find` calls.
+pub struct Batch {
+    tasks: Option<Sender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Batch {
+    pub fn new(image: &[i64], threads: usize) -> Self {
+        let image = Arc::new(image.to_vec());
+        let (tx, rx) = channel::<Task>();
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let (image, rx) = (image.clone(), rx.clone());
+                thread::spawn(move || {
+                    let mut com = Computer::from_image(image, None);
+                    loop {
+                        let task = rx.lock().unwrap().recv();
+                        match task {
+                            Ok(task) => task(&mut com),
+                            // the batch was dropped
+                            Err(_) => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+        Batch { tasks: Some(tx), workers }
+    }
+
+    /// `f` looks at each finished `Computer`, results come back in job order
+    pub fn run<J, F, R>(&self, jobs: J, f: F) -> Vec<R>
+        where J: IntoIterator<Item=Job>,
+              J::IntoIter: Send + 'static,
+              F: Fn(&mut Computer) -> R + Send + Sync + 'static,
+              R: Send + 'static {
+        self.spread(jobs, move |com| Some(f(com)), false)
+            .into_iter()
+            .map(|(_, r)| r)
+            .collect()
+    }
+
+    /// The first job in job order that `f` accepts. Jobs after a match aren't started, but ones
+    /// before it still finish in case they match too.
+    pub fn find<J, F, R>(&self, jobs: J, f: F) -> Option<(usize, R)>
+        where J: IntoIterator<Item=Job>,
+              J::IntoIter: Send + 'static,
+              F: Fn(&mut Computer) -> Option<R> + Send + Sync + 'static,
+              R: Send + 'static {
+        self.spread(jobs, f, true)
+            .into_iter()
+            .next()
+    }
+
+    /// Hands every thread a task that takes jobs off the shared queue until it's empty, and waits
+    /// for them all. A job that panics is passed on once the others are done.
+    fn spread<J, F, R>(&self, jobs: J, f: F, stop: bool) -> Vec<(usize, R)>
+        where J: IntoIterator<Item=Job>,
+              J::IntoIter: Send + 'static,
+              F: Fn(&mut Computer) -> Option<R> + Send + Sync + 'static,
+              R: Send + 'static {
+        let jobs: Arc<Mutex<Enumerate<J::IntoIter>>> = Arc::new(Mutex::new(jobs.into_iter().enumerate()));
+        let first = Arc::new(AtomicUsize::new(usize::MAX));
+        let f = Arc::new(f);
+        let (tx, rx) = channel::<Result<(usize, R), Box<dyn Any + Send>>>();
+
+        let tasks = self.tasks.as_ref().expect("the batch is only taken apart when dropped");
+        for _ in 0..self.workers.len() {
+            let (jobs, first, f, tx) = (jobs.clone(), first.clone(), f.clone(), tx.clone());
+            let task: Task = Box::new(move |com| {
+                let drain = panic::catch_unwind(AssertUnwindSafe(|| loop {
+                    let next = jobs.lock().unwrap().next();
+                    let (i, (patches, input)) = match next {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    // jobs come out in order, so everything after this is past the match too
+                    if i > first.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    com.reset(input);
+                    for (index, val) in patches {
+                        com.patch(index, val);
+                    }
+                    com.compute();
+                    if let Some(r) = f(com) {
+                        if stop {
+                            first.fetch_min(i, Ordering::SeqCst);
+                        }
+                        tx.send(Ok((i, r))).unwrap();
+                    }
+                }));
+                if let Err(payload) = drain {
+                    // stop the other threads taking more jobs, nothing will look at them
+                    first.store(0, Ordering::SeqCst);
+                    tx.send(Err(payload)).unwrap();
+                }
+            });
+            tasks.send(task).expect("batch threads only stop when the batch is dropped");
+        }
+        drop(tx);
+
+        let mut results = Vec::new();
+        let mut failed = None;
+        for msg in rx {
+            match msg {
+                Ok(result) => results.push(result),
+                Err(payload) => failed = failed.or(Some(payload)),
+            }
+        }
+        if let Some(payload) = failed {
+            panic::resume_unwind(payload);
+        }
+        results.sort_by_key(|&(i, _)| i);
+        results
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        // closing the queue lets each thread finish up and return
+        self.tasks.take();
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Batch, Job};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    // outputs its input times ten
+    const TIMES_TEN: [i64; 10] = [3, 9, 1002, 9, 10, 9, 4, 9, 99, 0];
+
+    fn jobs(n: i64) -> impl Iterator<Item=Job> {
+        (0..n).map(|i| (vec![], vec![i]))
+    }
+
+    #[test]
+    fn results_come_back_in_job_order() {
+        let batch = Batch::new(&TIMES_TEN, 4);
+        let out = batch.run(jobs(500), |com| com.recv().unwrap());
+        assert_eq!(out, (0..500).map(|i| i * 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn patches_apply_to_a_fresh_image() {
+        let batch = Batch::new(&TIMES_TEN, 3);
+        // the multiplier is patched on odd jobs only, so a leftover patch would show
+        let jobs = (0..100).map(|i| (if i % 2 == 1 { vec![(4, 3)] } else { vec![] }, vec![i]));
+        let out = batch.run(jobs, |com| com.recv().unwrap());
+        assert_eq!(out, (0..100).map(|i| if i % 2 == 1 { i * 3 } else { i * 10 }).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn find_stops_early() {
+        for &threads in &[1, 4] {
+            let batch = Batch::new(&TIMES_TEN, threads);
+            let taken = Arc::new(AtomicUsize::new(0));
+            let counter = taken.clone();
+            let jobs = jobs(100_000).inspect(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+            // every job from 30 on matches, the first of them is the answer
+            let found = batch.find(jobs, |com| Some(com.recv().unwrap()).filter(|&out| out >= 300));
+            assert_eq!(found, Some((30, 300)));
+            let taken = taken.load(Ordering::SeqCst);
+            match threads {
+                // the match, then the one job it takes before it sees there's no point
+                1 => assert_eq!(taken, 32),
+                _ => assert!(taken < 100_000, "{} jobs taken", taken),
+            }
+        }
+    }
+
+    #[test]
+    fn threads_are_kept_between_calls() {
+        let batch = Batch::new(&TIMES_TEN, 2);
+        let mut ids = HashSet::new();
+        for _ in 0..5 {
+            ids.extend(batch.run(jobs(50), |_| thread::current().id()));
+        }
+        assert!(ids.len() <= 2);
+        assert!(!ids.contains(&thread::current().id()));
+    }
+
+    #[test]
+    #[should_panic(expected = "bad job")]
+    fn panics_reach_the_caller() {
+        let batch = Batch::new(&TIMES_TEN, 2);
+        batch.run(jobs(10), |com| if com.recv() == Some(50) { panic!("bad job") });
+    }
+}
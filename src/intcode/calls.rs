@@ -0,0 +1,133 @@
+use crate::intcode::Computer;
+use std::fmt::{Display, Formatter, Error};
+
+// how many writes back a return address may have been stored before the jump that makes the call
+const STORED: usize = 8;
+
+/// One call, recognised by a jump to `func` whose fallthrough address `ret` was just written to memory
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Frame {
+    pub func: usize,
+    pub call_site: usize,
+    pub ret: usize,
+    pub rel_base: i64,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "function at {:#05x} called from {:#05x}", self.func, self.call_site)
+    }
+}
+
+/// A shadow call stack, only as good as the call/return heuristics. History keeps a copy with each
+/// instruction, so stepping back puts it back too.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    stored: Vec<i64>,
+}
+
+impl CallStack {
+    /// Outermost call first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub(super) fn store(&mut self, val: i64) {
+        if self.stored.len() == STORED {
+            self.stored.remove(0);
+        }
+        self.stored.push(val);
+    }
+
+    /// A taken jump. Jumping back to a return address pops to that frame, preferring one whose
+    /// relative base has been restored. Otherwise a jump whose fallthrough was just stored is a call.
+    pub(super) fn transfer(&mut self, from: usize, to: usize, next: usize, rel_base: i64) {
+        let ret = self.frames.iter().rposition(|frame| frame.ret == to && frame.rel_base == rel_base)
+            .or_else(|| self.frames.last().filter(|frame| frame.ret == to).map(|_| self.frames.len() - 1));
+        if let Some(depth) = ret {
+            self.frames.truncate(depth);
+        } else if self.stored.contains(&(next as i64)) {
+            self.frames.push(Frame { func: to, call_site: from, ret: next, rel_base });
+        }
+        self.stored.clear();
+    }
+}
+
+impl Display for CallStack {
+    /// Innermost call first, like a backtrace
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            writeln!(f, "{:>3}: {}", i, frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl Computer {
+    /// Starts keeping a shadow call stack, from an empty stack
+    pub fn track_calls(&mut self) {
+        self.calls = Some(CallStack::default());
+    }
+
+    pub fn stop_tracking_calls(&mut self) -> Option<CallStack> {
+        self.calls.take()
+    }
+
+    pub fn call_stack(&self) -> Option<&CallStack> {
+        self.calls.as_ref()
+    }
+
+    /// The function the program is currently in, if calls are tracked and it's in one
+    pub fn current_function(&self) -> Option<usize> {
+        self.calls.as_ref()?.frames.last().map(|frame| frame.func)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::Computer;
+    use crate::intcode::fuzz::{execute, Crash, Limits};
+    use crate::intcode::strict::Strictness;
+    use crate::intcode::testcase::{Case, Stop};
+
+    // [rb] = 9, call 10, halt at 9; the function at 10 outputs [30], which nothing wrote, and returns
+    const CALL: [i64; 15] = [109, 20, 21101, 9, 0, 0, 1105, 1, 10, 99, 4, 30, 2106, 0, 0];
+
+    #[test]
+    fn reports_carry_the_stack() {
+        let mut com = Computer::init(&CALL, None);
+        com.track_calls();
+        com.strict(Strictness::Warn);
+        com.track_usage();
+        com.compute();
+        let read = &com.uninit_reads()[0];
+        assert_eq!((read.ptr, read.index), (10, 30));
+        assert_eq!(read.stack.frames()[0].func, 10);
+        assert_eq!(com.usage().unwrap().busiest(), vec![(10, 2)]);
+
+        let case = Case {
+            name: "stops in a call".to_string(),
+            program: CALL.to_vec(),
+            patches: Vec::new(),
+            input: Vec::new(),
+            output: None,
+            memory: Vec::new(),
+            stop: Some(Stop::Halt),
+            steps: 4,
+        };
+        let failure = case.run().unwrap_err();
+        assert_eq!(failure.stack.frames()[0].call_site, 6);
+        assert!(failure.to_string().contains("function at 0x00a called from 0x006"));
+
+        let mut broken = CALL.to_vec();
+        broken[10] = 42;
+        let run = execute(&broken, &[], Limits::default());
+        assert_eq!(run.crash, Some(Crash::InvalidOpcode { ptr: 10, instr: 42 }));
+        assert_eq!(run.stack.frames()[0].func, 10);
+    }
+}
@@ -0,0 +1,1024 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Error};
+
+/// Compiles a small structured language down to an Intcode program.
+///
+/// ```text
+/// var memo[100];
+///
+/// fn fib(n) {
+///     if n < 2 { return n; }
+///     if memo[n] { return memo[n]; }
+///     memo[n] = fib(n - 1) + fib(n - 2);
+///     return memo[n];
+/// }
+///
+/// fn main() {
+///     var n = input();
+///     while n >= 0 {
+///         output(fib(n));
+///         n = input();
+///     }
+/// }
+/// ```
+///
+/// Values are integers, `var` declares a variable or a fixed size array (`var a[10];`) either globally
+/// or in a function, and there's `if`/`else`, `while`, functions with `return` and recursion, and
+/// `input()`/`output(x)`. Operators are `+ - *`, the comparisons, `!`, and `&&`/`||`, which only
+/// evaluate the right side when the left doesn't already decide the result. Intcode has no division so neither does the language. Execution starts at
+/// `main`.
+///
+/// Each call gets a frame on a stack addressed with the relative base: the return address at
+/// `rb[0]`, then the arguments, locals and temporaries. Arrays are indexed by writing the element's
+/// address into the next instruction. Local arrays aren't zeroed, globals are.
+pub fn compile(src: &str) -> Result<Vec<i64>, CompileError> {
+    let tokens = lex(src)?;
+    let program = Parser { tokens, pos: 0 }.program()?;
+    Gen::new(&program)?.program(&program)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}:{}: {}", self.line, self.col, self.msg)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+type Pos = (usize, usize);
+
+fn error<T>((line, col): Pos, msg: String) -> Result<T, CompileError> {
+    Err(CompileError { line, col, msg })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(i64),
+    Ident(String),
+    Sym(&'static str),
+    Eof,
+}
+
+impl Display for Tok {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Tok::Num(n) => write!(f, "`{}`", n),
+            Tok::Ident(name) => write!(f, "`{}`", name),
+            Tok::Sym(sym) => write!(f, "`{}`", sym),
+            Tok::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+const SYMS: [&str; 21] = [
+    "<=", ">=", "==", "!=", "&&", "||",
+    "(", ")", "{", "}", "[", "]", ",", ";", "=", "+", "-", "*", "<", ">", "!",
+];
+
+const KEYWORDS: [&str; 8] = ["fn", "var", "if", "else", "while", "return", "input", "output"];
+
+fn lex(src: &str) -> Result<Vec<(Tok, Pos)>, CompileError> {
+    let mut tokens = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split("//").next().unwrap();
+        let mut col = 0;
+        while col < line.len() {
+            let rest = &line[col..];
+            let pos = (i + 1, col + 1);
+            let c = rest.chars().next().unwrap();
+            if c.is_whitespace() {
+                col += c.len_utf8();
+            } else if c.is_ascii_digit() {
+                let len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+                match rest[..len].parse() {
+                    Ok(n) => tokens.push((Tok::Num(n), pos)),
+                    Err(_) => return error(pos, format!("`{}` is not a number", &rest[..len])),
+                }
+                col += len;
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                tokens.push((Tok::Ident(rest[..len].to_string()), pos));
+                col += len;
+            } else if let Some(sym) = SYMS.iter().find(|sym| rest.starts_with(*sym)) {
+                tokens.push((Tok::Sym(sym), pos));
+                col += sym.len();
+            } else {
+                return error(pos, format!("unexpected `{}`", c));
+            }
+        }
+    }
+    let end = (src.lines().count().max(1), 1);
+    tokens.push((Tok::Eof, end));
+    Ok(tokens)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Bin {
+    Add,
+    Sub,
+    Mul,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl Bin {
+    fn fold(self, a: i64, b: i64) -> i64 {
+        match self {
+            Bin::Add => a.wrapping_add(b),
+            Bin::Sub => a.wrapping_sub(b),
+            Bin::Mul => a.wrapping_mul(b),
+            Bin::Lt => (a < b) as i64,
+            Bin::Le => (a <= b) as i64,
+            Bin::Gt => (a > b) as i64,
+            Bin::Ge => (a >= b) as i64,
+            Bin::Eq => (a == b) as i64,
+            Bin::Ne => (a != b) as i64,
+            Bin::And => (a != 0 && b != 0) as i64,
+            Bin::Or => (a != 0 || b != 0) as i64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(i64),
+    Var(String, Pos),
+    Index(String, Box<Expr>, Pos),
+    Call(String, Vec<Expr>, Pos),
+    Input,
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Bin(Bin, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Var(String, Option<Expr>, Pos),
+    Array(String, usize, Pos),
+    Assign(String, Expr, Pos),
+    Store(String, Expr, Expr, Pos),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Output(Expr),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Func {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+    pos: Pos,
+}
+
+#[derive(Debug, Clone)]
+struct Program {
+    /// name, initial value or array length
+    globals: Vec<(String, Result<i64, usize>, Pos)>,
+    funcs: Vec<Func>,
+}
+
+struct Parser {
+    tokens: Vec<(Tok, Pos)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+
+    fn here(&self) -> Pos {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (Tok, Pos) {
+        let tok = self.tokens[self.pos].clone();
+        if tok.0 != Tok::Eof {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn is(&self, sym: &str) -> bool {
+        match self.peek() {
+            Tok::Sym(s) => *s == sym,
+            Tok::Ident(s) => s == sym,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        let is = self.is(sym);
+        if is {
+            self.pos += 1;
+        }
+        is
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), CompileError> {
+        if self.eat(sym) {
+            Ok(())
+        } else {
+            error(self.here(), format!("expected `{}`, found {}", sym, self.peek()))
+        }
+    }
+
+    fn ident(&mut self) -> Result<(String, Pos), CompileError> {
+        match self.next() {
+            (Tok::Ident(name), pos) if !KEYWORDS.contains(&name.as_str()) => Ok((name, pos)),
+            (tok, pos) => error(pos, format!("expected a name, found {}", tok)),
+        }
+    }
+
+    fn num(&mut self) -> Result<i64, CompileError> {
+        let neg = self.eat("-");
+        match self.next() {
+            (Tok::Num(n), _) => Ok(if neg { -n } else { n }),
+            (tok, pos) => error(pos, format!("expected a number, found {}", tok)),
+        }
+    }
+
+    fn program(mut self) -> Result<Program, CompileError> {
+        let mut program = Program { globals: Vec::new(), funcs: Vec::new() };
+        while *self.peek() != Tok::Eof {
+            if self.eat("var") {
+                let (name, pos) = self.ident()?;
+                let init = if self.eat("[") {
+                    let len = self.num()?;
+                    self.expect("]")?;
+                    if len < 0 {
+                        return error(pos, format!("`{}` can't have a negative length", name));
+                    }
+                    Err(len as usize)
+                } else if self.eat("=") {
+                    Ok(self.num()?)
+                } else {
+                    Ok(0)
+                };
+                self.expect(";")?;
+                program.globals.push((name, init, pos));
+            } else if self.eat("fn") {
+                let (name, pos) = self.ident()?;
+                self.expect("(")?;
+                let mut params = Vec::new();
+                while !self.eat(")") {
+                    if !params.is_empty() {
+                        self.expect(",")?;
+                    }
+                    params.push(self.ident()?.0);
+                }
+                let body = self.block()?;
+                program.funcs.push(Func { name, params, body, pos });
+            } else {
+                return error(self.here(), format!("expected `var` or `fn`, found {}", self.peek()));
+            }
+        }
+        Ok(program)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, CompileError> {
+        let stmt = if self.eat("var") {
+            let (name, pos) = self.ident()?;
+            if self.eat("[") {
+                let len = self.num()?;
+                self.expect("]")?;
+                if len < 0 {
+                    return error(pos, format!("`{}` can't have a negative length", name));
+                }
+                Stmt::Array(name, len as usize, pos)
+            } else if self.eat("=") {
+                Stmt::Var(name, Some(self.expr()?), pos)
+            } else {
+                Stmt::Var(name, None, pos)
+            }
+        } else if self.eat("if") {
+            return self.if_stmt();
+        } else if self.eat("while") {
+            let cond = self.expr()?;
+            return Ok(Stmt::While(cond, self.block()?));
+        } else if self.eat("return") {
+            match self.is(";") {
+                true => Stmt::Return(None),
+                false => Stmt::Return(Some(self.expr()?)),
+            }
+        } else if self.eat("output") {
+            self.expect("(")?;
+            let val = self.expr()?;
+            self.expect(")")?;
+            Stmt::Output(val)
+        } else {
+            let pos = self.here();
+            match self.expr()? {
+                Expr::Var(name, pos) if self.eat("=") => Stmt::Assign(name, self.expr()?, pos),
+                Expr::Index(name, index, pos) if self.eat("=") => Stmt::Store(name, *index, self.expr()?, pos),
+                _ if self.is("=") => return error(pos, "can only assign to a variable or array element".to_string()),
+                expr => Stmt::Expr(expr),
+            }
+        };
+        self.expect(";")?;
+        Ok(stmt)
+    }
+
+    fn if_stmt(&mut self) -> Result<Stmt, CompileError> {
+        let cond = self.expr()?;
+        let then = self.block()?;
+        let otherwise = if !self.eat("else") {
+            Vec::new()
+        } else if self.eat("if") {
+            vec![self.if_stmt()?]
+        } else {
+            self.block()?
+        };
+        Ok(Stmt::If(cond, then, otherwise))
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, CompileError> {
+        const LEVELS: [&[(&str, Bin)]; 5] = [
+            &[("||", Bin::Or)],
+            &[("&&", Bin::And)],
+            &[("==", Bin::Eq), ("!=", Bin::Ne), ("<=", Bin::Le), (">=", Bin::Ge), ("<", Bin::Lt), (">", Bin::Gt)],
+            &[("+", Bin::Add), ("-", Bin::Sub)],
+            &[("*", Bin::Mul)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(&(_, op)) = LEVELS[level].iter().find(|(sym, _)| self.is(sym)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else if self.eat("!") {
+            Ok(Expr::Not(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        if self.eat("input") {
+            self.expect("(")?;
+            self.expect(")")?;
+            return Ok(Expr::Input);
+        }
+        if let Tok::Num(n) = *self.peek() {
+            self.pos += 1;
+            return Ok(Expr::Num(n));
+        }
+        let (name, pos) = self.ident()?;
+        if self.eat("(") {
+            let mut args = Vec::new();
+            while !self.eat(")") {
+                if !args.is_empty() {
+                    self.expect(",")?;
+                }
+                args.push(self.expr()?);
+            }
+            Ok(Expr::Call(name, args, pos))
+        } else if self.eat("[") {
+            let index = self.expr()?;
+            self.expect("]")?;
+            Ok(Expr::Index(name, Box::new(index), pos))
+        } else {
+            Ok(Expr::Var(name, pos))
+        }
+    }
+}
+
+/// A word of output that may depend on things not known yet
+#[derive(Debug, Copy, Clone)]
+enum Word {
+    Lit(i64),
+    /// a label's address plus an offset
+    Label(usize, i64),
+    /// `mul * frame_size + offset` for the function being compiled
+    Frame(i64, i64),
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Operand {
+    Pos(Word),
+    Imm(Word),
+    Rel(Word),
+}
+
+use Operand::*;
+use Word::*;
+
+fn konst(op: Operand) -> Option<i64> {
+    match op {
+        Imm(Lit(n)) => Some(n),
+        _ => None,
+    }
+}
+
+/// Where a name lives
+#[derive(Debug, Copy, Clone)]
+enum Var {
+    Local(i64),
+    LocalArray(i64),
+    Global(usize),
+    GlobalArray(usize),
+}
+
+struct Gen {
+    code: Vec<Word>,
+    labels: Vec<Option<usize>>,
+    /// label and arity
+    funcs: HashMap<String, (usize, usize)>,
+    globals: HashMap<String, Var>,
+    ret: usize,
+    sp: usize,
+    stack: usize,
+    track_sp: bool,
+    locals: HashMap<String, Var>,
+    temp_base: i64,
+    next_temp: i64,
+    max_temp: i64,
+}
+
+impl Gen {
+    fn new(program: &Program) -> Result<Self, CompileError> {
+        let mut gen = Gen {
+            code: Vec::new(),
+            labels: Vec::new(),
+            funcs: HashMap::new(),
+            globals: HashMap::new(),
+            ret: 0,
+            sp: 0,
+            stack: 0,
+            track_sp: false,
+            locals: HashMap::new(),
+            temp_base: 0,
+            next_temp: 0,
+            max_temp: 0,
+        };
+        gen.ret = gen.label();
+        gen.sp = gen.label();
+        gen.stack = gen.label();
+        for (name, init, pos) in &program.globals {
+            let label = gen.label();
+            let var = match init {
+                Ok(_) => Var::Global(label),
+                Err(_) => Var::GlobalArray(label),
+            };
+            if gen.globals.insert(name.clone(), var).is_some() {
+                return error(*pos, format!("`{}` is already defined", name));
+            }
+        }
+        for func in &program.funcs {
+            let label = gen.label();
+            if gen.funcs.insert(func.name.clone(), (label, func.params.len())).is_some() {
+                return error(func.pos, format!("`{}` is already defined", func.name));
+            }
+        }
+        gen.track_sp = program.funcs.iter().any(|func| has_local_array(&func.body));
+        Ok(gen)
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, code: i64, ops: &[Operand]) {
+        let mut instr = code;
+        let mut words = Vec::new();
+        for (i, op) in ops.iter().enumerate() {
+            let (mode, word) = match *op {
+                Pos(word) => (0, word),
+                Imm(word) => (1, word),
+                Rel(word) => (2, word),
+            };
+            instr += mode * 10_i64.pow(i as u32 + 2);
+            words.push(word);
+        }
+        self.code.push(Lit(instr));
+        self.code.extend(words);
+    }
+
+    fn temp(&mut self) -> Operand {
+        let temp = self.next_temp;
+        self.next_temp += 1;
+        self.max_temp = self.max_temp.max(self.next_temp);
+        Rel(Lit(temp))
+    }
+
+    fn jump(&mut self, label: usize) {
+        self.emit(6, &[Imm(Lit(0)), Imm(Label(label, 0))]);
+    }
+
+    fn program(mut self, program: &Program) -> Result<Vec<i64>, CompileError> {
+        let main = match self.funcs.get("main") {
+            Some(&(main, 0)) => main,
+            Some(_) => return error(program.funcs.iter().find(|f| f.name == "main").unwrap().pos, "`main` can't take parameters".to_string()),
+            None => return error((1, 1), "there's no `main` function".to_string()),
+        };
+        let halt = self.label();
+        self.emit(9, &[Imm(Label(self.stack, 0))]);
+        self.emit(1, &[Imm(Label(halt, 0)), Imm(Lit(0)), Rel(Lit(0))]);
+        self.jump(main);
+        self.place(halt);
+        self.emit(99, &[]);
+
+        for func in &program.funcs {
+            self.func(func)?;
+        }
+
+        self.place(self.ret);
+        self.code.push(Lit(0));
+        self.place(self.sp);
+        self.code.push(Label(self.stack, 0));
+        for (name, init, _) in &program.globals {
+            match (init, self.globals[name]) {
+                (Ok(n), Var::Global(label)) => {
+                    self.place(label);
+                    self.code.push(Lit(*n));
+                }
+                (Err(len), Var::GlobalArray(label)) => {
+                    self.place(label);
+                    self.code.extend((0..*len).map(|_| Lit(0)));
+                }
+                _ => unreachable!(),
+            }
+        }
+        self.place(self.stack);
+
+        let labels = self.labels;
+        Ok(self.code.into_iter()
+            .map(|word| match word {
+                Lit(n) => n,
+                Label(label, off) => labels[label].expect("label never placed") as i64 + off,
+                Frame(..) => unreachable!("frame sizes are filled in per function"),
+            })
+            .collect())
+    }
+
+    fn func(&mut self, func: &Func) -> Result<(), CompileError> {
+        let start = self.code.len();
+        let label = self.funcs[&func.name].0;
+        self.place(label);
+
+        self.locals.clear();
+        for (i, param) in func.params.iter().enumerate() {
+            if self.locals.insert(param.clone(), Var::Local(i as i64 + 1)).is_some() {
+                return error(func.pos, format!("`{}` has two parameters called `{}`", func.name, param));
+            }
+        }
+        let mut size = func.params.len() as i64 + 1;
+        self.declare(&func.body, &mut size)?;
+        self.temp_base = size;
+        self.next_temp = size;
+        self.max_temp = size;
+
+        self.block(&func.body)?;
+        self.emit(1, &[Imm(Lit(0)), Imm(Lit(0)), Pos(Label(self.ret, 0))]);
+        self.emit(6, &[Imm(Lit(0)), Rel(Lit(0))]);
+
+        let frame = self.max_temp;
+        for word in &mut self.code[start..] {
+            if let Frame(mul, off) = *word {
+                *word = Lit(mul * frame + off);
+            }
+        }
+        Ok(())
+    }
+
+    /// Locals are hoisted to the top of the function so the frame layout is known up front
+    fn declare(&mut self, body: &[Stmt], size: &mut i64) -> Result<(), CompileError> {
+        for stmt in body {
+            match stmt {
+                Stmt::Var(name, _, pos) | Stmt::Array(name, _, pos) => {
+                    let var = match stmt {
+                        Stmt::Array(_, len, _) => {
+                            *size += *len as i64;
+                            Var::LocalArray(*size - *len as i64)
+                        }
+                        _ => {
+                            *size += 1;
+                            Var::Local(*size - 1)
+                        }
+                    };
+                    if self.locals.insert(name.clone(), var).is_some() {
+                        return error(*pos, format!("`{}` is already defined in this function", name));
+                    }
+                }
+                Stmt::If(_, then, otherwise) => {
+                    self.declare(then, size)?;
+                    self.declare(otherwise, size)?;
+                }
+                Stmt::While(_, body) => self.declare(body, size)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str, pos: Pos) -> Result<Var, CompileError> {
+        match self.locals.get(name).or_else(|| self.globals.get(name)) {
+            Some(&var) => Ok(var),
+            None => error(pos, format!("`{}` isn't defined", name)),
+        }
+    }
+
+    fn scalar(&self, name: &str, pos: Pos) -> Result<Operand, CompileError> {
+        match self.lookup(name, pos)? {
+            Var::Local(slot) => Ok(Rel(Lit(slot))),
+            Var::Global(label) => Ok(Pos(Label(label, 0))),
+            Var::LocalArray(_) | Var::GlobalArray(_) => error(pos, format!("`{}` is an array", name)),
+        }
+    }
+
+    /// Writes the address of `name[index]` into the word `patch`, or gives the element directly when
+    /// its address is known now
+    fn element(&mut self, name: &str, index: &Expr, pos: Pos, patch: Word) -> Result<Option<Operand>, CompileError> {
+        let index = self.expr(index)?;
+        let base = match (self.lookup(name, pos)?, konst(index)) {
+            (Var::GlobalArray(label), Some(i)) => return Ok(Some(Pos(Label(label, i)))),
+            (Var::GlobalArray(label), None) => Imm(Label(label, 0)),
+            (Var::LocalArray(slot), _) => {
+                let base = self.temp();
+                self.emit(1, &[Pos(Label(self.sp, 0)), Imm(Lit(slot)), base]);
+                base
+            }
+            _ => return error(pos, format!("`{}` isn't an array", name)),
+        };
+        self.emit(1, &[base, index, Pos(patch)]);
+        Ok(None)
+    }
+
+    fn block(&mut self, body: &[Stmt]) -> Result<(), CompileError> {
+        for stmt in body {
+            self.next_temp = self.temp_base;
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Var(name, init, pos) => {
+                let val = match init {
+                    Some(init) => self.expr(init)?,
+                    None => Imm(Lit(0)),
+                };
+                let var = self.scalar(name, *pos)?;
+                self.emit(1, &[val, Imm(Lit(0)), var]);
+            }
+            Stmt::Array(..) => {}
+            Stmt::Assign(name, val, pos) => {
+                let var = self.scalar(name, *pos)?;
+                let val = self.expr(val)?;
+                self.emit(1, &[val, Imm(Lit(0)), var]);
+            }
+            Stmt::Store(name, index, val, pos) => {
+                let val = self.expr(val)?;
+                let patch = self.label();
+                match self.element(name, index, *pos, Label(patch, 3))? {
+                    Some(elem) => self.emit(1, &[val, Imm(Lit(0)), elem]),
+                    None => {
+                        self.place(patch);
+                        self.emit(1, &[val, Imm(Lit(0)), Pos(Lit(0))]);
+                    }
+                }
+            }
+            Stmt::If(cond, then, otherwise) => {
+                let cond = self.expr(cond)?;
+                let (other, end) = (self.label(), self.label());
+                self.emit(6, &[cond, Imm(Label(other, 0))]);
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.jump(end);
+                }
+                self.place(other);
+                self.block(otherwise)?;
+                self.place(end);
+            }
+            Stmt::While(cond, body) => {
+                let (top, end) = (self.label(), self.label());
+                self.place(top);
+                let cond = self.expr(cond)?;
+                self.emit(6, &[cond, Imm(Label(end, 0))]);
+                self.block(body)?;
+                self.jump(top);
+                self.place(end);
+            }
+            Stmt::Return(val) => {
+                let val = match val {
+                    Some(val) => self.expr(val)?,
+                    None => Imm(Lit(0)),
+                };
+                self.emit(1, &[val, Imm(Lit(0)), Pos(Label(self.ret, 0))]);
+                self.emit(6, &[Imm(Lit(0)), Rel(Lit(0))]);
+            }
+            Stmt::Output(val) => {
+                let val = self.expr(val)?;
+                self.emit(4, &[val]);
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Operand, CompileError> {
+        Ok(match expr {
+            Expr::Num(n) => Imm(Lit(*n)),
+            Expr::Var(name, pos) => self.scalar(name, *pos)?,
+            Expr::Index(name, index, pos) => {
+                let patch = self.label();
+                match self.element(name, index, *pos, Label(patch, 1))? {
+                    Some(elem) => elem,
+                    None => {
+                        let dest = self.temp();
+                        self.place(patch);
+                        self.emit(1, &[Pos(Lit(0)), Imm(Lit(0)), dest]);
+                        dest
+                    }
+                }
+            }
+            Expr::Call(name, args, pos) => self.call(name, args, *pos)?,
+            Expr::Input => {
+                let dest = self.temp();
+                self.emit(3, &[dest]);
+                dest
+            }
+            Expr::Neg(val) => {
+                let val = self.expr(val)?;
+                match konst(val) {
+                    Some(n) => Imm(Lit(n.wrapping_neg())),
+                    None => {
+                        let dest = self.temp();
+                        self.emit(2, &[val, Imm(Lit(-1)), dest]);
+                        dest
+                    }
+                }
+            }
+            Expr::Not(val) => {
+                let val = self.expr(val)?;
+                match konst(val) {
+                    Some(n) => Imm(Lit((n == 0) as i64)),
+                    None => {
+                        let dest = self.temp();
+                        self.emit(8, &[val, Imm(Lit(0)), dest]);
+                        dest
+                    }
+                }
+            }
+            Expr::Bin(op @ (Bin::And | Bin::Or), a, b) => {
+                let a = self.expr(a)?;
+                match (op, konst(a)) {
+                    (Bin::And, Some(0)) => return Ok(Imm(Lit(0))),
+                    (Bin::Or, Some(n)) if n != 0 => return Ok(Imm(Lit(1))),
+                    (_, Some(_)) => return self.truth(b),
+                    _ => {}
+                }
+                let (dest, end) = (self.temp(), self.label());
+                self.emit(8, &[a, Imm(Lit(0)), dest]);
+                self.emit(8, &[dest, Imm(Lit(0)), dest]);
+                // `a` decides it when it's false for `&&` or true for `||`
+                self.emit(if *op == Bin::And { 6 } else { 5 }, &[dest, Imm(Label(end, 0))]);
+                let b = self.truth(b)?;
+                self.emit(1, &[b, Imm(Lit(0)), dest]);
+                self.place(end);
+                dest
+            }
+            Expr::Bin(op, a, b) => {
+                let (a, b) = (self.expr(a)?, self.expr(b)?);
+                if let (Some(a), Some(b)) = (konst(a), konst(b)) {
+                    return Ok(Imm(Lit(op.fold(a, b))));
+                }
+                let dest = self.temp();
+                match op {
+                    Bin::Add => self.emit(1, &[a, b, dest]),
+                    Bin::Mul => self.emit(2, &[a, b, dest]),
+                    Bin::Sub => match konst(b) {
+                        Some(b) => self.emit(1, &[a, Imm(Lit(b.wrapping_neg())), dest]),
+                        None => {
+                            self.emit(2, &[b, Imm(Lit(-1)), dest]);
+                            self.emit(1, &[a, dest, dest]);
+                        }
+                    },
+                    Bin::Lt => self.emit(7, &[a, b, dest]),
+                    Bin::Gt => self.emit(7, &[b, a, dest]),
+                    Bin::Eq => self.emit(8, &[a, b, dest]),
+                    Bin::Le | Bin::Ge | Bin::Ne => {
+                        match op {
+                            Bin::Le => self.emit(7, &[b, a, dest]),
+                            Bin::Ge => self.emit(7, &[a, b, dest]),
+                            _ => self.emit(8, &[a, b, dest]),
+                        }
+                        self.emit(8, &[dest, Imm(Lit(0)), dest]);
+                    }
+                    Bin::And | Bin::Or => unreachable!("short-circuit operators are handled above"),
+                }
+                dest
+            }
+        })
+    }
+
+    /// `expr` as 0 or 1
+    fn truth(&mut self, expr: &Expr) -> Result<Operand, CompileError> {
+        let val = self.expr(expr)?;
+        if let Some(n) = konst(val) {
+            return Ok(Imm(Lit((n != 0) as i64)));
+        }
+        let dest = self.temp();
+        self.emit(8, &[val, Imm(Lit(0)), dest]);
+        self.emit(8, &[dest, Imm(Lit(0)), dest]);
+        Ok(dest)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], pos: Pos) -> Result<Operand, CompileError> {
+        let (label, arity) = match self.funcs.get(name) {
+            Some(&func) => func,
+            None => return error(pos, format!("there's no function called `{}`", name)),
+        };
+        if args.len() != arity {
+            return error(pos, format!("wrong number of arguments to `{}`: expected {}, found {}", name, arity, args.len()));
+        }
+        // an argument's own calls could clobber a global read by an earlier one, so copy them first
+        let mut vals = Vec::new();
+        for arg in args {
+            let val = self.expr(arg)?;
+            let val = match val {
+                Pos(_) => {
+                    let temp = self.temp();
+                    self.emit(1, &[val, Imm(Lit(0)), temp]);
+                    temp
+                }
+                val => val,
+            };
+            vals.push(val);
+        }
+        for (i, val) in vals.into_iter().enumerate() {
+            self.emit(1, &[val, Imm(Lit(0)), Rel(Frame(1, i as i64 + 1))]);
+        }
+        let ret = self.label();
+        self.emit(1, &[Imm(Label(ret, 0)), Imm(Lit(0)), Rel(Frame(1, 0))]);
+        self.emit(9, &[Imm(Frame(1, 0))]);
+        if self.track_sp {
+            self.emit(1, &[Pos(Label(self.sp, 0)), Imm(Frame(1, 0)), Pos(Label(self.sp, 0))]);
+        }
+        self.jump(label);
+        self.place(ret);
+        self.emit(9, &[Imm(Frame(-1, 0))]);
+        if self.track_sp {
+            self.emit(1, &[Pos(Label(self.sp, 0)), Imm(Frame(-1, 0)), Pos(Label(self.sp, 0))]);
+        }
+        let dest = self.temp();
+        self.emit(1, &[Pos(Label(self.ret, 0)), Imm(Lit(0)), dest]);
+        Ok(dest)
+    }
+}
+
+fn has_local_array(body: &[Stmt]) -> bool {
+    body.iter().any(|stmt| match stmt {
+        Stmt::Array(..) => true,
+        Stmt::If(_, then, otherwise) => has_local_array(then) || has_local_array(otherwise),
+        Stmt::While(_, body) => has_local_array(body),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile;
+    use crate::intcode::Computer;
+
+    fn run(src: &str, input: &[i64]) -> Vec<i64> {
+        let mem = compile(src).unwrap();
+        let mut com = Computer::init(&mem, input.iter().copied());
+        com.compute();
+        assert!(com.is_done);
+        com.recv_all().collect()
+    }
+
+    #[test]
+    fn recursive_calls_and_global_arrays() {
+        let src = "
+            var memo[100];
+
+            fn fib(n) {
+                if n < 2 { return n; }
+                if memo[n] { return memo[n]; }
+                memo[n] = fib(n - 1) + fib(n - 2);
+                return memo[n];
+            }
+
+            fn main() {
+                var n = input();
+                while n >= 0 {
+                    output(fib(n));
+                    n = input();
+                }
+            }
+        ";
+        assert_eq!(run(src, &[10, 1, 50, -1]), vec![55, 1, 12_586_269_025]);
+    }
+
+    #[test]
+    fn locals_survive_calls() {
+        let src = "
+            var calls = 0;
+
+            fn sum(n) {
+                var a[5];
+                var i = 0;
+                calls = calls + 1;
+                while i < 5 {
+                    a[i] = n * i;
+                    i = i + 1;
+                }
+                var total = 0;
+                if n > 1 { total = sum(n - 1); }
+                i = 0;
+                while i < 5 {
+                    total = total + a[i];
+                    i = i + 1;
+                }
+                return total;
+            }
+
+            fn main() {
+                var x = 7;
+                output(sum(4));
+                output(x);
+                output(calls);
+            }
+        ";
+        // 10 * (4 + 3 + 2 + 1)
+        assert_eq!(run(src, &[]), vec![100, 7, 4]);
+    }
+
+    #[test]
+    fn and_or_short_circuit() {
+        let src = "
+            fn side(x) {
+                output(x);
+                return x;
+            }
+
+            fn main() {
+                if side(0) && side(1) { output(9); }
+                if side(2) || side(3) { output(9); }
+                output(side(4) && side(0));
+                output(0 || side(5));
+                output(1 && 0);
+            }
+        ";
+        assert_eq!(run(src, &[]), vec![0, 2, 9, 4, 0, 0, 5, 1, 0]);
+    }
+
+    #[test]
+    fn errors_point_at_the_source() {
+        let err = compile("fn main() {\n    output(x);\n}").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 12));
+        assert!(compile("fn main() { f(1); }\nfn f(a, b) {}").is_err());
+    }
+}
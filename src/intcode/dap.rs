@@ -0,0 +1,687 @@
+use crate::intcode::Computer;
+use crate::intcode::calls::CallStack;
+use crate::intcode::decode::{decode, disassemble};
+use crate::intcode::fuzz::Crash;
+use crate::intcode::json::Json;
+use crate::intcode::link::SymbolMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+// how far `continue` runs before giving control back, so a spinning program can't hang the adapter
+const RUN_LIMIT: usize = 10_000_000;
+// how far memory may grow before a write counts as a crash
+const MEM_LIMIT: usize = 1 << 24;
+const HISTORY: usize = 100_000;
+const REGISTERS: i64 = 1;
+const MEMORY: i64 = 2;
+const QUEUES: i64 = 3;
+
+/// Maps source lines to addresses, either from an assembler listing or from a disassembly of the
+/// program. A listing line that starts with a number followed by `:` is the code at that address.
+struct Listing {
+    path: Option<String>,
+    text: String,
+    addrs: Vec<Option<usize>>,
+}
+
+impl Listing {
+    fn parse(path: &str, text: String) -> Self {
+        let addrs = text.lines()
+            .map(|line| {
+                let line = line.trim_start();
+                let digits = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+                match line[digits..].trim_start().starts_with(':') {
+                    true => line[..digits].parse().ok(),
+                    false => None,
+                }
+            })
+            .collect();
+        Listing { path: Some(path.to_string()), text, addrs }
+    }
+
+    fn disassemble(mem: &[i64]) -> Self {
+        let mut text = String::new();
+        let mut addrs = Vec::new();
+        let mut instrs = disassemble(mem).into_iter().peekable();
+        let mut addr = 0;
+        while addr < mem.len() {
+            match instrs.peek() {
+                Some(instr) if instr.addr == addr => {
+                    text.push_str(&format!("{:>5}: {}\n", addr, instr));
+                    addr = instr.next();
+                    addrs.push(Some(instr.addr));
+                    instrs.next();
+                }
+                _ => {
+                    text.push_str(&format!("{:>5}: data {}\n", addr, mem[addr]));
+                    addrs.push(Some(addr));
+                    addr += 1;
+                }
+            }
+        }
+        Listing { path: None, text, addrs }
+    }
+
+    /// The closest line at or before `addr`, lines start at 1
+    fn line_of(&self, addr: usize) -> Option<usize> {
+        self.addrs.iter()
+            .enumerate()
+            .filter_map(|(i, a)| a.filter(|&a| a <= addr).map(|a| (a, i + 1)))
+            .max_by_key(|&(a, line)| (a, std::cmp::Reverse(line)))
+            .map(|(_, line)| line)
+    }
+
+    fn addr_of(&self, line: usize) -> Option<usize> {
+        self.addrs.get(line.checked_sub(1)?).copied().flatten()
+    }
+
+    fn source(&self) -> Json {
+        match &self.path {
+            Some(path) => Json::obj(vec![("name", path.as_str().into()), ("path", path.as_str().into())]),
+            None => Json::obj(vec![("name", "disassembly".into()), ("sourceReference", 1_i64.into())]),
+        }
+    }
+}
+
+struct Target {
+    com: Computer,
+    listing: Listing,
+    symbols: Option<SymbolMap>,
+    breakpoints: HashSet<usize>,
+    stop_on_entry: bool,
+    // where the last instruction ran, for crash reports
+    from: usize,
+}
+
+impl Target {
+    /// Runs one instruction if the VM can do so safely
+    fn step(&mut self) -> Result<bool, Crash> {
+        self.com.check_step(self.from, MEM_LIMIT)?;
+        let ptr = self.com.ptr;
+        self.from = ptr;
+        let com = &mut self.com;
+        panic::catch_unwind(AssertUnwindSafe(|| com.step())).map_err(|err| {
+            let msg = err.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| err.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Crash::Panic { ptr, msg }
+        })
+    }
+
+    fn depth(&self) -> usize {
+        self.com.call_stack().map_or(0, CallStack::depth)
+    }
+
+    /// Steps until `done` holds, at most `limit` times. `Some("breakpoint")` if it stopped on one
+    /// first, `None` if the program halted, wants input or `done` was reached.
+    fn run_until<F: Fn(&Target) -> bool>(&mut self, limit: usize, done: F) -> Result<Option<&'static str>, Crash> {
+        for _ in 0..limit {
+            if !self.step()? || done(self) {
+                return Ok(None);
+            }
+            if self.breakpoints.contains(&self.com.ptr) {
+                return Ok(Some("breakpoint"));
+            }
+        }
+        Ok(Some("pause"))
+    }
+}
+
+enum Run {
+    Continue,
+    Next,
+    StepIn,
+    StepOut,
+    StepBack,
+    ReverseContinue,
+}
+
+struct Server<W: Write> {
+    out: W,
+    seq: i64,
+    target: Option<Target>,
+}
+
+/// Serves the Debug Adapter Protocol over a pair of streams, stdin and stdout for an editor
+pub fn serve<R: BufRead, W: Write>(mut reader: R, writer: W) -> io::Result<()> {
+    let mut server = Server { out: writer, seq: 1, target: None };
+    while let Some(msg) = read_message(&mut reader)? {
+        let req = match Json::parse(&msg) {
+            Ok(req) => req,
+            Err(err) => {
+                server.event("output", Json::obj(vec![("category", "stderr".into()), ("output", format!("bad message: {}\n", err).into())]))?;
+                continue;
+            }
+        };
+        if !server.handle(&req)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            if len.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = n.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; len.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, mut msg: Json) -> io::Result<()> {
+        if let Json::Obj(pairs) = &mut msg {
+            pairs.insert(0, ("seq".to_string(), self.seq.into()));
+        }
+        self.seq += 1;
+        let body = msg.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.out.flush()
+    }
+
+    fn respond(&mut self, req: &Json, body: Result<Json, String>) -> io::Result<()> {
+        let mut msg = vec![
+            ("type", "response".into()),
+            ("request_seq", req.get("seq").cloned().unwrap_or(Json::Null)),
+            ("command", req.get("command").cloned().unwrap_or(Json::Null)),
+            ("success", body.is_ok().into()),
+        ];
+        match body {
+            Ok(body) => msg.push(("body", body)),
+            Err(err) => msg.push(("message", err.into())),
+        }
+        self.send(Json::obj(msg))
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(Json::obj(vec![("type", "event".into()), ("event", event.into()), ("body", body)]))
+    }
+
+    fn handle(&mut self, req: &Json) -> io::Result<bool> {
+        let empty_args = Json::obj(vec![]);
+        let args = req.get("arguments").unwrap_or(&empty_args);
+        let command = req.get("command").and_then(Json::as_str).unwrap_or("");
+        match command {
+            "initialize" => {
+                self.respond(req, Ok(Json::obj(vec![
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsStepBack", true.into()),
+                    ("supportsEvaluateForHovers", false.into()),
+                ])))?;
+                self.event("initialized", Json::obj(vec![]))?;
+            }
+            "launch" => {
+                let res = self.launch(args);
+                self.respond(req, res.map(|_| Json::obj(vec![])))?;
+            }
+            "setBreakpoints" => {
+                let res = self.set_breakpoints(args);
+                self.respond(req, res)?;
+            }
+            "configurationDone" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                match self.target.as_ref().map(|target| target.stop_on_entry) {
+                    Some(true) => self.stopped("entry", None)?,
+                    Some(false) => self.resume(Run::Continue)?,
+                    None => {}
+                }
+            }
+            "threads" => {
+                let thread = Json::obj(vec![("id", 1_i64.into()), ("name", "intcode".into())]);
+                self.respond(req, Ok(Json::obj(vec![("threads", vec![thread].into())])))?;
+            }
+            "stackTrace" => {
+                let res = self.stack_trace();
+                self.respond(req, res)?;
+            }
+            "scopes" => {
+                let len = self.target.as_ref().map_or(0, |target| target.com.mem.len());
+                let scope = |name: &str, reference: i64, indexed: Option<usize>| {
+                    let mut pairs = vec![("name", name.into()), ("variablesReference", reference.into()), ("expensive", indexed.is_some().into())];
+                    if let Some(len) = indexed {
+                        pairs.push(("indexedVariables", len.into()));
+                    }
+                    Json::obj(pairs)
+                };
+                let scopes = vec![scope("Registers", REGISTERS, None), scope("Memory", MEMORY, Some(len)), scope("Queues", QUEUES, None)];
+                self.respond(req, Ok(Json::obj(vec![("scopes", scopes.into())])))?;
+            }
+            "variables" => {
+                let res = self.variables(args);
+                self.respond(req, res)?;
+            }
+            "continue" => {
+                self.respond(req, Ok(Json::obj(vec![("allThreadsContinued", true.into())])))?;
+                self.resume(Run::Continue)?;
+            }
+            "next" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                self.resume(Run::Next)?;
+            }
+            "stepIn" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                self.resume(Run::StepIn)?;
+            }
+            "stepOut" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                self.resume(Run::StepOut)?;
+            }
+            "stepBack" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                self.resume(Run::StepBack)?;
+            }
+            "reverseContinue" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                self.resume(Run::ReverseContinue)?;
+            }
+            "pause" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                self.stopped("pause", None)?;
+            }
+            "evaluate" => {
+                let res = self.evaluate(args);
+                self.respond(req, res)?;
+            }
+            "source" => {
+                let text = self.target.as_ref().map(|target| target.listing.text.clone());
+                self.respond(req, text.map(|text| Json::obj(vec![("content", text.into())])).ok_or_else(|| "nothing launched".to_string()))?;
+            }
+            "disconnect" | "terminate" => {
+                self.respond(req, Ok(Json::obj(vec![])))?;
+                return Ok(false);
+            }
+            command => self.respond(req, Err(format!("`{}` isn't supported", command)))?,
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<(), String> {
+        let path = args.get("program").and_then(Json::as_str).ok_or("launch needs a `program` path")?;
+        let src = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let mem = Computer::load(&src).map_err(|err| format!("{}:{}", path, err))?;
+        let listing = match args.get("listing").and_then(Json::as_str) {
+            Some(listing) => Listing::parse(listing, fs::read_to_string(listing).map_err(|err| format!("{}: {}", listing, err))?),
+            None => Listing::disassemble(&mem),
+        };
+        let symbols = match args.get("symbols").and_then(Json::as_str) {
+            Some(symbols) => {
+                let text = fs::read_to_string(symbols).map_err(|err| format!("{}: {}", symbols, err))?;
+                Some(SymbolMap::parse(&text).ok_or_else(|| format!("{}: not a symbol map", symbols))?)
+            }
+            None => None,
+        };
+        let input: Vec<i64> = args.get("input")
+            .and_then(Json::as_array)
+            .map(|vals| vals.iter().filter_map(Json::as_i64).collect())
+            .unwrap_or_default();
+        let mut com = Computer::init(&mem, input);
+        com.record_history(HISTORY, 1_000);
+        com.track_calls();
+        self.target = Some(Target {
+            com,
+            listing,
+            symbols,
+            breakpoints: HashSet::new(),
+            stop_on_entry: args.get("stopOnEntry").and_then(Json::as_bool).unwrap_or(true),
+            from: 0,
+        });
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let target = self.target.as_mut().ok_or("nothing launched")?;
+        let lines: Vec<usize> = args.get("breakpoints")
+            .and_then(Json::as_array)
+            .map(|bps| bps.iter().filter_map(|bp| bp.get("line")?.as_i64()).map(|line| line as usize).collect())
+            .unwrap_or_default();
+        target.breakpoints.clear();
+        let bps = lines.into_iter()
+            .map(|line| match target.listing.addr_of(line) {
+                Some(addr) => {
+                    target.breakpoints.insert(addr);
+                    Json::obj(vec![("verified", true.into()), ("line", line.into())])
+                }
+                None => Json::obj(vec![("verified", false.into()), ("line", line.into()), ("message", "no code on this line".into())]),
+            })
+            .collect::<Vec<_>>();
+        Ok(Json::obj(vec![("breakpoints", bps.into())]))
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let target = self.target.as_ref().ok_or("nothing launched")?;
+        let ptr = target.com.ptr;
+        let calls = target.com.call_stack().map_or(&[][..], |calls| calls.frames());
+        // the innermost frame is where the program is now, the rest are the call sites that led there
+        let locations = std::iter::once(ptr).chain(calls.iter().rev().map(|frame| frame.call_site));
+        let funcs = calls.iter().rev().map(|frame| Some(frame.func)).chain(std::iter::once(None));
+        let frames = locations.zip(funcs)
+            .enumerate()
+            .map(|(id, (addr, func))| {
+                let name = match func {
+                    Some(func) => target.symbols.as_ref()
+                        .and_then(|symbols| symbols.name_of(func))
+                        .unwrap_or_else(|| format!("function at {:#05x}", func)),
+                    None => "main".to_string(),
+                };
+                let name = match decode(&target.com.mem, addr) {
+                    Some(instr) => format!("{} ({}: {})", name, addr, instr),
+                    None => format!("{} ({})", name, addr),
+                };
+                Json::obj(vec![
+                    ("id", id.into()),
+                    ("name", name.into()),
+                    ("source", target.listing.source()),
+                    ("line", target.listing.line_of(addr).unwrap_or(0).into()),
+                    ("column", 1_i64.into()),
+                    ("instructionPointerReference", addr.to_string().into()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let total = frames.len();
+        Ok(Json::obj(vec![("stackFrames", frames.into()), ("totalFrames", total.into())]))
+    }
+
+    fn variables(&self, args: &Json) -> Result<Json, String> {
+        let target = self.target.as_ref().ok_or("nothing launched")?;
+        let com = &target.com;
+        let var = |name: String, val: String| Json::obj(vec![("name", name.into()), ("value", val.into()), ("variablesReference", 0_i64.into())]);
+        let vars = match args.get("variablesReference").and_then(Json::as_i64) {
+            Some(REGISTERS) => vec![
+                var("ptr".to_string(), com.ptr.to_string()),
+                var("rel_base".to_string(), com.rel_base.to_string()),
+                var("steps".to_string(), com.steps.to_string()),
+                var("is_done".to_string(), com.is_done.to_string()),
+            ],
+            Some(MEMORY) => {
+                let start = args.get("start").and_then(Json::as_i64).unwrap_or(0) as usize;
+                let count = args.get("count").and_then(Json::as_i64).map_or(com.mem.len(), |n| n as usize);
+                com.mem.iter()
+                    .enumerate()
+                    .skip(start)
+                    .take(count)
+                    .map(|(i, n)| var(format!("[{}]", i), n.to_string()))
+                    .collect()
+            }
+            Some(QUEUES) => vec![
+                var("input".to_string(), format!("{:?}", com.input)),
+                var("output".to_string(), format!("{:?}", com.output)),
+            ],
+            _ => return Err("unknown variables reference".to_string()),
+        };
+        Ok(Json::obj(vec![("variables", vars.into())]))
+    }
+
+    /// `[addr]` reads memory, `input 1 2 3` queues input
+    fn evaluate(&mut self, args: &Json) -> Result<Json, String> {
+        let target = self.target.as_mut().ok_or("nothing launched")?;
+        let expr = args.get("expression").and_then(Json::as_str).unwrap_or("").trim();
+        let result = if let Some(vals) = expr.strip_prefix("input") {
+            let vals = vals.split_whitespace()
+                .map(|n| n.parse::<i64>().map_err(|_| format!("`{}` isn't a number", n)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let n = vals.len();
+            target.com.send_all(vals.into_iter());
+            format!("queued {} inputs", n)
+        } else if expr.starts_with('[') && expr.ends_with(']') {
+            let addr = expr[1..expr.len() - 1].trim().parse::<usize>().map_err(|_| format!("bad address in `{}`", expr))?;
+            target.com.mem.get(addr).copied().unwrap_or(0).to_string()
+        } else {
+            return Err(format!("can't evaluate `{}`", expr));
+        };
+        Ok(Json::obj(vec![("result", result.into()), ("variablesReference", 0_i64.into())]))
+    }
+
+    /// Stepping into a call is one instruction, stepping over it runs until the call stack is back to
+    /// where it was, and stepping out runs until it's shallower. Breakpoints stop all of them.
+    fn resume(&mut self, run: Run) -> io::Result<()> {
+        let target = match self.target.as_mut() {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        let depth = target.depth();
+        let reason = match run {
+            Run::StepIn => target.run_until(1, |_| true).map(|reason| reason.unwrap_or("step")),
+            Run::Next => target.run_until(RUN_LIMIT, |target| target.depth() <= depth).map(|reason| reason.unwrap_or("step")),
+            Run::StepOut => target.run_until(RUN_LIMIT, |target| target.depth() < depth).map(|reason| reason.unwrap_or("step")),
+            Run::Continue => target.run_until(RUN_LIMIT, |_| false).map(|reason| reason.unwrap_or("pause")),
+            Run::StepBack => {
+                target.com.step_back();
+                Ok("step")
+            }
+            Run::ReverseContinue => {
+                let mut reason = "entry";
+                while target.com.step_back() {
+                    if target.breakpoints.contains(&target.com.ptr) {
+                        reason = "breakpoint";
+                        break;
+                    }
+                }
+                Ok(reason)
+            }
+        };
+        if let Run::StepBack | Run::ReverseContinue = run {
+            target.from = target.com.ptr;
+        }
+
+        let outputs: Vec<i64> = target.com.recv_all().collect();
+        for n in outputs {
+            self.event("output", Json::obj(vec![("category", "stdout".into()), ("output", format!("{}\n", n).into())]))?;
+        }
+        let target = self.target.as_ref().unwrap();
+        match reason {
+            // the program stays where it is, so the editor can look at what went wrong
+            Err(crash) => self.stopped("exception", Some(&crash.to_string())),
+            Ok(_) if target.com.is_done => {
+                self.event("terminated", Json::obj(vec![]))?;
+                self.event("exited", Json::obj(vec![("exitCode", 0_i64.into())]))
+            }
+            Ok(_) if decode(&target.com.mem, target.com.ptr).is_some_and(|instr| instr.code == 3) && target.com.input.is_empty() => {
+                self.stopped("pause", Some("waiting for input"))
+            }
+            Ok(reason) => self.stopped(reason, None),
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<&str>) -> io::Result<()> {
+        let mut body = vec![("reason", reason.into()), ("threadId", 1_i64.into()), ("allThreadsStopped", true.into())];
+        if let Some(text) = text {
+            body.push(("description", text.into()));
+            body.push(("text", text.into()));
+        }
+        self.event("stopped", Json::obj(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_message, serve, Listing, MEMORY, REGISTERS};
+    use crate::intcode::json::Json;
+    use std::io::Cursor;
+    use std::{env, fs};
+
+    // main calls a function at 20 that reads a number and doubles it, then main prints it
+    //  0: arb 100
+    //  2: [rb] = 9              the return address
+    //  6: jnz 1, 20             the call
+    //  9: out [50]
+    // 11: halt
+    // 20: in [50]
+    // 22: [50] = [50] * 2
+    // 26: jz 0, [rb]            the return
+    const PROGRAM: [i64; 29] = [
+        109, 100, 21101, 9, 0, 0, 1105, 1, 20, 4, 50, 99, 0, 0, 0, 0, 0, 0, 0, 0,
+        3, 50, 1002, 50, 2, 50, 2106, 0, 0,
+    ];
+
+    /// Sends `commands` to a fresh adapter running `program`, then returns everything it sent back
+    fn session(name: &str, program: &[i64], commands: &[(&str, Json)]) -> Vec<Json> {
+        let path = env::temp_dir().join(format!("intcode-dap-{}-{}.int", name, std::process::id()));
+        let text: Vec<String> = program.iter().map(i64::to_string).collect();
+        fs::write(&path, text.join(",")).unwrap();
+        let launch = Json::obj(vec![
+            ("program", path.to_str().unwrap().into()),
+            ("input", vec![Json::Int(21)].into()),
+            ("stopOnEntry", true.into()),
+        ]);
+        let script = vec![("initialize", Json::obj(vec![])), ("launch", launch)].into_iter()
+            .chain(commands.iter().cloned())
+            .enumerate()
+            .map(|(i, (command, args))| {
+                let body = Json::obj(vec![
+                    ("seq", (i + 1).into()),
+                    ("type", "request".into()),
+                    ("command", command.into()),
+                    ("arguments", args),
+                ]).to_string();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            })
+            .collect::<String>();
+
+        let mut out = Vec::new();
+        serve(Cursor::new(script), &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut reader = Cursor::new(out);
+        let mut msgs = Vec::new();
+        while let Some(msg) = read_message(&mut reader).unwrap() {
+            msgs.push(Json::parse(&msg).unwrap());
+        }
+        msgs
+    }
+
+    /// The response to request `seq`, and the events that followed it
+    fn reply(msgs: &[Json], seq: i64) -> (&Json, Vec<&Json>) {
+        let at = msgs.iter()
+            .position(|msg| msg.get("request_seq").and_then(Json::as_i64) == Some(seq))
+            .unwrap();
+        let events = msgs[at + 1..].iter()
+            .take_while(|msg| msg.get("type").and_then(Json::as_str) == Some("event"))
+            .collect();
+        (&msgs[at], events)
+    }
+
+    fn stopped(msgs: &[Json], seq: i64) -> String {
+        let (_, events) = reply(msgs, seq);
+        let event = events.iter()
+            .find(|event| event.get("event").and_then(Json::as_str) == Some("stopped"))
+            .unwrap();
+        event.get("body").and_then(|body| body.get("reason")).and_then(Json::as_str).unwrap().to_string()
+    }
+
+    /// Instruction pointers of the stack frames, innermost first
+    fn frames(msgs: &[Json], seq: i64) -> Vec<usize> {
+        let (res, _) = reply(msgs, seq);
+        res.get("body").and_then(|body| body.get("stackFrames")).and_then(Json::as_array).unwrap().iter()
+            .map(|frame| frame.get("instructionPointerReference").and_then(Json::as_str).unwrap().parse().unwrap())
+            .collect()
+    }
+
+    fn registers(msgs: &[Json], seq: i64) -> Vec<(String, String)> {
+        let (res, _) = reply(msgs, seq);
+        res.get("body").and_then(|body| body.get("variables")).and_then(Json::as_array).unwrap().iter()
+            .map(|var| (var.get("name").and_then(Json::as_str).unwrap().to_string(), var.get("value").and_then(Json::as_str).unwrap().to_string()))
+            .collect()
+    }
+
+    fn no_args() -> Json {
+        Json::obj(vec![("threadId", 1_i64.into())])
+    }
+
+    #[test]
+    fn scripted_session() {
+        let line = Listing::disassemble(&PROGRAM).line_of(22).unwrap();
+        let msgs = session("scripted", &PROGRAM, &[
+            ("setBreakpoints", Json::obj(vec![("breakpoints", vec![Json::obj(vec![("line", line.into())])].into())])),
+            ("configurationDone", Json::obj(vec![])),
+            ("next", no_args()),
+            ("next", no_args()),
+            ("stepIn", no_args()),
+            ("stackTrace", no_args()),
+            ("variables", Json::obj(vec![("variablesReference", REGISTERS.into())])),
+            ("continue", no_args()),
+            ("stepOut", no_args()),
+            ("stackTrace", no_args()),
+            ("stepBack", no_args()),
+            ("variables", Json::obj(vec![("variablesReference", MEMORY.into()), ("start", 50_i64.into()), ("count", 1_i64.into())])),
+            ("reverseContinue", no_args()),
+            ("stackTrace", no_args()),
+            ("continue", no_args()),
+            ("disconnect", Json::obj(vec![])),
+        ]);
+
+        let (init, events) = reply(&msgs, 1);
+        assert_eq!(init.get("success"), Some(&Json::Bool(true)));
+        assert_eq!(events[0].get("event").and_then(Json::as_str), Some("initialized"));
+        assert_eq!(reply(&msgs, 2).0.get("success"), Some(&Json::Bool(true)));
+        let bps = reply(&msgs, 3).0.get("body").and_then(|body| body.get("breakpoints")).cloned().unwrap();
+        assert_eq!(bps.as_array().unwrap()[0].get("verified"), Some(&Json::Bool(true)));
+        assert_eq!(stopped(&msgs, 4), "entry");
+
+        // next, next, then into the call
+        assert_eq!(stopped(&msgs, 5), "step");
+        assert_eq!(stopped(&msgs, 6), "step");
+        assert_eq!(stopped(&msgs, 7), "step");
+        assert_eq!(frames(&msgs, 8), vec![20, 6]);
+        assert!(registers(&msgs, 9).contains(&("ptr".to_string(), "20".to_string())));
+
+        // runs the input to the breakpoint, then out of the function
+        assert_eq!(stopped(&msgs, 10), "breakpoint");
+        assert_eq!(stopped(&msgs, 11), "step");
+        assert_eq!(frames(&msgs, 12), vec![9]);
+
+        // back over the return, the doubling has happened
+        assert_eq!(stopped(&msgs, 13), "step");
+        assert_eq!(registers(&msgs, 14), vec![("[50]".to_string(), "42".to_string())]);
+        assert_eq!(stopped(&msgs, 15), "breakpoint");
+        assert_eq!(frames(&msgs, 16), vec![22, 6]);
+
+        let (_, events) = reply(&msgs, 17);
+        let names: Vec<&str> = events.iter().filter_map(|event| event.get("event").and_then(Json::as_str)).collect();
+        assert_eq!(names, vec!["output", "terminated", "exited"]);
+        assert_eq!(events[0].get("body").and_then(|body| body.get("output")), Some(&Json::Str("42\n".to_string())));
+    }
+
+    #[test]
+    fn next_steps_over_calls() {
+        let msgs = session("next", &PROGRAM, &[
+            ("configurationDone", Json::obj(vec![])),
+            ("next", no_args()),
+            ("next", no_args()),
+            ("next", no_args()),
+            ("stackTrace", no_args()),
+        ]);
+        assert_eq!(stopped(&msgs, 6), "step");
+        assert_eq!(frames(&msgs, 7), vec![9]);
+    }
+
+    #[test]
+    fn bad_instructions_stop_with_an_exception() {
+        let msgs = session("crash", &[1105, 1, 3, 42], &[
+            ("configurationDone", Json::obj(vec![])),
+            ("continue", no_args()),
+            ("continue", no_args()),
+            ("stackTrace", no_args()),
+        ]);
+        assert_eq!(stopped(&msgs, 4), "exception");
+        assert_eq!(stopped(&msgs, 5), "exception");
+        assert_eq!(frames(&msgs, 6), vec![3]);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter, Error};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Param {
+    Pos(i64),
+    Imm(i64),
+    Rel(i64),
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Param::Pos(n) => write!(f, "[{}]", n),
+            Param::Imm(n) => write!(f, "{}", n),
+            Param::Rel(n) => write!(f, "rb[{}]", n),
+        }
+    }
+}
+
+/// A statically decoded standard instruction
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Instr {
+    pub addr: usize,
+    pub code: i64,
+    pub params: [Param; 3],
+    pub len: usize,
+}
+
+impl Instr {
+    pub fn name(&self) -> &'static str {
+        match self.code {
+            1 => "add",
+            2 => "mul",
+            3 => "in",
+            4 => "out",
+            5 => "jnz",
+            6 => "jz",
+            7 => "lt",
+            8 => "eq",
+            9 => "arb",
+            _ => "halt",
+        }
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params[..self.len - 1]
+    }
+
+    pub fn reads(&self) -> &[Param] {
+        match self.code {
+            1 | 2 | 7 | 8 => &self.params[..2],
+            4 | 5 | 6 | 9 => &self.params[..self.len - 1],
+            _ => &[],
+        }
+    }
+
+    pub fn write(&self) -> Option<Param> {
+        match self.code {
+            1 | 2 | 7 | 8 => Some(self.params[2]),
+            3 => Some(self.params[0]),
+            _ => None,
+        }
+    }
+
+    pub fn is_jump(&self) -> bool {
+        self.code == 5 || self.code == 6
+    }
+
+    pub fn next(&self) -> usize {
+        self.addr + self.len
+    }
+
+    /// Whether this is a jump that can be taken to an address only known at runtime
+    pub fn is_computed_jump(&self) -> bool {
+        match (self.code, self.params[0], self.params[1]) {
+            (_, _, Param::Imm(_)) => false,
+            (5, Param::Imm(c), _) => c != 0,
+            (6, Param::Imm(c), _) => c == 0,
+            _ => self.is_jump(),
+        }
+    }
+
+    /// Where execution can go after this instruction, as far as the operands tell. A taken jump to its
+    /// own address moves on to the next instruction like the VM does, and computed jump targets are
+    /// left out.
+    pub fn successors(&self) -> Vec<usize> {
+        let jump = |target: i64| if target as usize == self.addr { self.next() } else { target as usize };
+        match (self.code, self.params[0], self.params[1]) {
+            (99, _, _) => vec![],
+            (5, Param::Imm(c), Param::Imm(target)) | (6, Param::Imm(c), Param::Imm(target)) => {
+                if (c != 0) == (self.code == 5) {
+                    vec![jump(target)]
+                } else {
+                    vec![self.next()]
+                }
+            }
+            (5, Param::Imm(c), _) | (6, Param::Imm(c), _) if (c != 0) == (self.code == 5) => vec![],
+            (5, _, Param::Imm(target)) | (6, _, Param::Imm(target)) => vec![jump(target), self.next()],
+            _ => vec![self.next()],
+        }
+    }
+
+    /// The instruction back in its memory form
+    pub fn encode(&self) -> Vec<i64> {
+        let mut words = vec![self.code];
+        for (i, param) in self.params().iter().enumerate() {
+            let (mode, n) = match *param {
+                Param::Pos(n) => (0, n),
+                Param::Imm(n) => (1, n),
+                Param::Rel(n) => (2, n),
+            };
+            words[0] += mode * 10_i64.pow(i as u32 + 2);
+            words.push(n);
+        }
+        words
+    }
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.name())?;
+        for (i, param) in self.params().iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, param)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn decode(mem: &[i64], addr: usize) -> Option<Instr> {
+    let instr = *mem.get(addr)?;
+    if instr < 0 {
+        return None;
+    }
+    let code = instr % 100;
+    let len = match code {
+        1 | 2 | 7 | 8 => 4,
+        5 | 6 => 3,
+        3 | 4 | 9 => 2,
+        99 => 1,
+        _ => return None,
+    };
+    if instr / 10_i64.pow(len as u32 + 1) != 0 {
+        return None;
+    }
+    let mut params = [Param::Imm(0); 3];
+    for offset in 1..len {
+        let n = *mem.get(addr + offset)?;
+        params[offset - 1] = match (instr / 10_i64.pow(offset as u32 + 1)) % 10 {
+            0 => Param::Pos(n),
+            1 => Param::Imm(n),
+            2 => Param::Rel(n),
+            _ => return None,
+        };
+    }
+    let instr = Instr { addr, code, params, len };
+    // the VM would write into the instruction itself, leave that to the VM
+    if let Some(Param::Imm(_)) = instr.write() {
+        return None;
+    }
+    Some(instr)
+}
+
+/// Decodes straight through `mem`, stepping over words that aren't instructions one at a time
+pub fn disassemble(mem: &[i64]) -> Vec<Instr> {
+    let mut instrs = Vec::new();
+    let mut addr = 0;
+    while addr < mem.len() {
+        match decode(mem, addr) {
+            Some(instr) => {
+                addr = instr.next();
+                instrs.push(instr);
+            }
+            None => addr += 1,
+        }
+    }
+    instrs
+}
+
+/// Instructions reachable from `roots` through fallthrough and immediate jumps, even where they
+/// overlap each other. Code that's only reached through computed jumps isn't found.
+pub fn trace(mem: &[i64], roots: &[usize]) -> BTreeMap<usize, Instr> {
+    let mut found = BTreeMap::new();
+    let mut todo = roots.to_vec();
+    while let Some(addr) = todo.pop() {
+        if found.contains_key(&addr) {
+            continue;
+        }
+        let instr = match decode(mem, addr) {
+            Some(instr) => instr,
+            None => continue,
+        };
+        found.insert(addr, instr);
+        todo.extend(instr.successors());
+    }
+    found
+}
+
+/// Like `trace`, but leaves out instructions that overlap, since a jump into the middle of another
+/// instruction means at least one of them isn't what it looks like
+pub fn discover(mem: &[i64], roots: &[usize]) -> BTreeMap<usize, Instr> {
+    let mut found = trace(mem, roots);
+    let mut end = 0;
+    let mut overlapping = HashSet::new();
+    let mut last = 0;
+    for (&addr, instr) in &found {
+        if addr < end {
+            overlapping.insert(addr);
+            overlapping.insert(last);
+        }
+        end = end.max(instr.next());
+        last = addr;
+    }
+    found.retain(|addr, _| !overlapping.contains(addr));
+    found
+}
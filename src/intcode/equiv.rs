@@ -0,0 +1,335 @@
+use crate::intcode::Computer;
+use crate::intcode::decode::decode;
+use crate::intcode::fuzz::{dictionary, Crash, Limits};
+use crate::intcode::testcase::Stop;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter, Error};
+use std::panic::{self, AssertUnwindSafe};
+
+// instructions kept at the end of each trace
+const TRACE: usize = 12;
+// values the shortest generated inputs are built from
+const ALPHABET: usize = 12;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Event {
+    Input { step: u64, val: i64 },
+    Output { step: u64, val: i64 },
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Event::Input { step, val } => write!(f, "{:>8}  in  {}", step, val),
+            Event::Output { step, val } => write!(f, "{:>8}  out {}", step, val),
+        }
+    }
+}
+
+/// What one program did with an input sequence
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Trace {
+    pub events: Vec<Event>,
+    pub stop: Stop,
+    pub crash: Option<Crash>,
+    /// the last instructions run, oldest first
+    pub tail: Vec<String>,
+}
+
+impl Trace {
+    pub fn output(&self) -> Vec<i64> {
+        self.events.iter()
+            .filter_map(|event| match *event {
+                Event::Output { val, .. } => Some(val),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for event in &self.events {
+            writeln!(f, "  {}", event)?;
+        }
+        match &self.crash {
+            Some(crash) => writeln!(f, "  stopped on crash: {}", crash)?,
+            None => writeln!(f, "  stopped on {}", self.stop)?,
+        }
+        writeln!(f, "  last instructions:")?;
+        for line in &self.tail {
+            writeln!(f, "    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `image` with `input` handed over as the program asks for it, until it halts, wants more,
+/// crashes or runs out of steps
+pub fn trace(image: &[i64], input: &[i64], limits: Limits) -> Trace {
+    let mut com = Computer::init(image, input.iter().copied());
+    let mut events = Vec::new();
+    let mut tail = VecDeque::with_capacity(TRACE);
+    let mut crash = None;
+    let mut from = 0;
+    let stop = loop {
+        if com.steps >= limits.steps {
+            break Stop::StepLimit;
+        }
+        if let Err(err) = com.check_step(from, limits.mem) {
+            crash = Some(err);
+            break Stop::Crash;
+        }
+        let ptr = com.ptr;
+        from = ptr;
+        if tail.len() == TRACE {
+            tail.pop_front();
+        }
+        tail.push_back(match decode(&com.mem, ptr) {
+            Some(instr) => format!("{:>6}: {}", ptr, instr),
+            None => format!("{:>6}: {}", ptr, com.mem[ptr]),
+        });
+        let (step, waiting, queued) = (com.steps, com.input.front().copied(), com.input.len());
+        let running = match panic::catch_unwind(AssertUnwindSafe(|| com.step())) {
+            Ok(running) => running,
+            Err(err) => {
+                let msg = err.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| err.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                crash = Some(Crash::Panic { ptr, msg });
+                break Stop::Crash;
+            }
+        };
+        if let Some(val) = waiting.filter(|_| com.input.len() < queued) {
+            events.push(Event::Input { step, val });
+        }
+        if let Some(val) = com.output.pop_front() {
+            events.push(Event::Output { step, val });
+        }
+        if !running {
+            break if com.is_done { Stop::Halt } else { Stop::Input };
+        }
+    };
+    Trace { events, stop, crash, tail: tail.into() }
+}
+
+/// Whether the traces show the programs behaving differently. Only the output and how each run ended
+/// count, and any crash matches any other. A run that hit the step limit might still have produced
+/// more, and so might two runs that both want more input, which lets programs ask for input at
+/// different times. Those only differ where one has output something the other didn't.
+pub fn differs(a: &Trace, b: &Trace) -> bool {
+    let (out_a, out_b) = (a.output(), b.output());
+    let prefix = out_a.iter().zip(&out_b).any(|(x, y)| x != y);
+    match (a.stop, b.stop) {
+        (Stop::StepLimit, Stop::StepLimit) | (Stop::Input, Stop::Input) => prefix,
+        (Stop::StepLimit, _) => prefix || out_a.len() > out_b.len(),
+        (_, Stop::StepLimit) => prefix || out_b.len() > out_a.len(),
+        _ => out_a != out_b || a.stop != b.stop,
+    }
+}
+
+/// An input the two programs disagree on
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub input: Vec<i64>,
+    pub a: Trace,
+    pub b: Trace,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        writeln!(f, "programs differ on input {:?}", self.input)?;
+        writeln!(f, "first program:")?;
+        write!(f, "{}", self.a)?;
+        writeln!(f, "second program:")?;
+        write!(f, "{}", self.b)
+    }
+}
+
+/// Looks for an input two programs behave differently on. Given inputs are tried first, then every
+/// short sequence of constants from the programs, shortest first, then random ones.
+pub struct Checker {
+    a: Vec<i64>,
+    b: Vec<i64>,
+    limits: Limits,
+    inputs: Vec<Vec<i64>>,
+    exhaustive: usize,
+    random: usize,
+    rng: u64,
+    runs: usize,
+}
+
+impl Checker {
+    pub fn new(a: &[i64], b: &[i64]) -> Self {
+        Checker {
+            a: a.to_vec(),
+            b: b.to_vec(),
+            limits: Limits { steps: 100_000, mem: 1 << 20 },
+            inputs: Vec::new(),
+            exhaustive: 2_000,
+            random: 2_000,
+            rng: 0x2545_f491_4f6c_dd1d,
+            runs: 0,
+        }
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// An input to try before the generated ones
+    pub fn input(mut self, input: Vec<i64>) -> Self {
+        self.inputs.push(input);
+        self
+    }
+
+    /// How many shortest-first and random inputs to generate
+    pub fn generate(mut self, exhaustive: usize, random: usize) -> Self {
+        self.exhaustive = exhaustive;
+        self.random = random;
+        self
+    }
+
+    pub fn rng_seed(mut self, seed: u64) -> Self {
+        self.rng = seed.max(1);
+        self
+    }
+
+    /// Input sequences tried so far, both programs are run on each
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    pub fn run(&mut self, input: &[i64]) -> Option<Mismatch> {
+        self.runs += 1;
+        let (a, b) = (trace(&self.a, input, self.limits), trace(&self.b, input, self.limits));
+        match differs(&a, &b) {
+            true => Some(Mismatch { input: input.to_vec(), a, b }),
+            false => None,
+        }
+    }
+
+    /// The shortest input found that the programs disagree on, or `None` if they agreed on everything
+    pub fn check(&mut self) -> Option<Mismatch> {
+        self.search().map(|mismatch| self.minimise(mismatch))
+    }
+
+    fn search(&mut self) -> Option<Mismatch> {
+        let mut found: Option<Mismatch> = None;
+        for input in std::mem::take(&mut self.inputs) {
+            if let Some(mismatch) = self.run(&input) {
+                if found.as_ref().is_none_or(|found| mismatch.input.len() < found.input.len()) {
+                    found = Some(mismatch);
+                }
+            }
+        }
+
+        let mut alphabet = dictionary(&self.a);
+        alphabet.extend(dictionary(&self.b));
+        alphabet.sort_unstable_by_key(|&n| (n.unsigned_abs(), n < 0));
+        alphabet.dedup();
+        let short = &alphabet[..alphabet.len().min(ALPHABET)];
+        // odometer over `short`, one more digit each time it wraps
+        let mut digits: Vec<usize> = Vec::new();
+        for _ in 0..self.exhaustive {
+            if found.as_ref().is_some_and(|found| found.input.len() <= digits.len()) {
+                break;
+            }
+            let input: Vec<i64> = digits.iter().map(|&d| short[d]).collect();
+            if let Some(mismatch) = self.run(&input) {
+                found = Some(mismatch);
+                break;
+            }
+            match digits.iter().rposition(|&d| d + 1 < short.len()) {
+                Some(i) => {
+                    digits[i] += 1;
+                    digits[i + 1..].iter_mut().for_each(|d| *d = 0);
+                }
+                None => digits = vec![0; digits.len() + 1],
+            }
+        }
+        if found.is_some() {
+            return found;
+        }
+
+        for _ in 0..self.random {
+            let len = 1 + self.next(16);
+            let input: Vec<i64> = (0..len)
+                .map(|_| match self.next(2) {
+                    0 => alphabet[self.next(alphabet.len())],
+                    _ => self.next(256) as i64 - 128,
+                })
+                .collect();
+            if let Some(mismatch) = self.run(&input) {
+                return Some(mismatch);
+            }
+        }
+        None
+    }
+
+    /// Drops values, then zeroes them, as long as the programs still disagree
+    fn minimise(&mut self, mut best: Mismatch) -> Mismatch {
+        let mut i = 0;
+        while i < best.input.len() {
+            let mut input = best.input.clone();
+            input.remove(i);
+            match self.run(&input) {
+                Some(mismatch) => best = mismatch,
+                None => i += 1,
+            }
+        }
+        for i in 0..best.input.len() {
+            if best.input[i] != 0 {
+                let mut input = best.input.clone();
+                input[i] = 0;
+                if let Some(mismatch) = self.run(&input) {
+                    best = mismatch;
+                }
+            }
+        }
+        best
+    }
+
+    /// xorshift, good enough to pick inputs
+    fn next(&mut self, below: usize) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % below.max(1) as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{trace, Checker, Event};
+    use crate::intcode::fuzz::Limits;
+    use crate::intcode::testcase::Stop;
+
+    // in [5]; out [5]; halt
+    const ECHO: [i64; 5] = [3, 5, 4, 5, 99];
+    // in [20]; eq [20] 7 -> [21]; add [20] [21] -> [20]; out [20]; halt, an echo except that 7 gives 8
+    const ALMOST: [i64; 13] = [3, 20, 1008, 20, 7, 21, 1, 20, 21, 20, 4, 20, 99];
+
+    #[test]
+    fn traces_inputs_and_outputs() {
+        let found = trace(&ECHO, &[5], Limits::default());
+        assert_eq!(found.events, vec![Event::Input { step: 0, val: 5 }, Event::Output { step: 1, val: 5 }]);
+        assert_eq!(found.stop, Stop::Halt);
+        assert_eq!(trace(&ECHO, &[], Limits::default()).stop, Stop::Input);
+    }
+
+    #[test]
+    fn same_programs_agree() {
+        let mut checker = Checker::new(&ECHO, &ECHO).generate(200, 200);
+        assert!(checker.check().is_none());
+        assert_eq!(checker.runs(), 400);
+    }
+
+    #[test]
+    fn finds_the_input_they_disagree_on() {
+        let mismatch = Checker::new(&ECHO, &ALMOST).check().unwrap();
+        assert_eq!(mismatch.input, vec![7]);
+        assert_eq!((mismatch.a.output(), mismatch.b.output()), (vec![7], vec![8]));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::intcode::{Computer, Mode};
+use crate::intcode::replay::Event;
+use std::fmt::{Debug, Formatter, Error};
+use std::sync::Arc;
+
+pub type Handler = dyn Fn(&mut Computer, &Args) -> Effect + Send + Sync;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Effect {
+    /// continue at the next instruction
+    Next,
+    Jump(usize),
+    /// move past the instruction, then return from `compute`
+    Pause,
+    /// return from `compute` without moving past the instruction, like `Input` on an empty queue
+    Wait,
+    Halt,
+}
+
+pub struct Extension {
+    pub code: i64,
+    pub name: String,
+    pub nparams: usize,
+    pub nwrites: usize,
+    handler: Arc<Handler>,
+}
+
+impl Extension {
+    pub fn new<F>(code: i64, name: &str, nparams: usize, nwrites: usize, handler: F) -> Self
+        where F: Fn(&mut Computer, &Args) -> Effect + Send + Sync + 'static {
+        Extension {
+            code,
+            name: name.to_string(),
+            nparams,
+            nwrites,
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Words the instruction takes up, the opcode and its operands
+    pub fn width(&self) -> usize {
+        self.nparams + self.nwrites + 1
+    }
+}
+
+impl Debug for Extension {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.debug_struct("Extension")
+            .field("code", &self.code)
+            .field("name", &self.name)
+            .field("nparams", &self.nparams)
+            .field("nwrites", &self.nwrites)
+            .finish()
+    }
+}
+
+/// The operands of an extension instruction, reads first and then writes, with modes already resolved.
+#[derive(Debug, Clone)]
+pub struct Args {
+    modes: Vec<Mode>,
+}
+
+impl Args {
+    pub fn len(&self) -> usize {
+        self.modes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modes.is_empty()
+    }
+
+    pub fn index(&self, i: usize) -> usize {
+        self.modes[i].index()
+    }
+
+    pub fn read(&self, com: &mut Computer, i: usize) -> i64 {
+        com.read(&self.modes[i])
+    }
+
+    pub fn write(&self, com: &mut Computer, i: usize, val: i64) {
+        com.write(&self.modes[i], val)
+    }
+}
+
+impl Computer {
+    pub fn register(&mut self, ext: Extension) {
+        match ext.code {
+            1..=9 | 99 => panic!("opcode {} is a standard Intcode instruction", ext.code),
+            code if !(0..100).contains(&code) => panic!("opcode {} doesn't fit in two digits", code),
+            _ => {}
+        }
+        self.extensions.insert(ext.code, Arc::new(ext));
+    }
+
+    pub fn extension(&self, code: i64) -> Option<&Extension> {
+        self.extensions.get(&code).map(|ext| &**ext)
+    }
+
+    pub fn copy_extensions(&mut self, other: &Computer) {
+        self.extensions = other.extensions.clone();
+    }
+
+    pub(super) fn step_ext(&mut self, code: i64) -> bool {
+        let ext = self.extensions[&code].clone();
+        let args = Args {
+            modes: (1..ext.width()).map(|offset| Mode::from(self, offset)).collect(),
+        };
+        let effect = (ext.handler)(self, &args);
+        if self.taint.is_some() && effect != Effect::Wait {
+            let next = match effect {
+                Effect::Jump(ptr) => ptr,
+                _ => self.ptr + ext.width(),
+            };
+            self.propagate_ext(&args.modes[..ext.nparams], &args.modes[ext.nparams..], next);
+        }
+        if effect != Effect::Wait {
+            let func = self.current_function();
+            if let Some(usage) = &mut self.usage {
+                usage.executed(self.ptr, ext.width(), func);
+            }
+            if let (Effect::Halt, Some(session)) = (effect, &mut self.session) {
+                session.push(Event::Halt { step: self.steps });
+            }
+            self.steps += 1;
+            if let Some(history) = &mut self.history {
+                history.commit();
+            }
+        }
+        match effect {
+            Effect::Next => {
+                self.ptr += ext.width();
+                true
+            }
+            Effect::Jump(ptr) => {
+                let from = self.ptr;
+                self.ptr = ptr;
+                if let Some(calls) = &mut self.calls {
+                    calls.transfer(from, ptr, from + ext.width(), self.rel_base);
+                }
+                true
+            }
+            Effect::Pause => {
+                self.ptr += ext.width();
+                false
+            }
+            Effect::Wait => {
+                if let Some(history) = &mut self.history {
+                    history.cancel();
+                }
+                false
+            }
+            Effect::Halt => {
+                self.is_done = true;
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Effect, Extension};
+    use crate::intcode::Computer;
+    use crate::intcode::replay::Event;
+
+    fn jump() -> Extension {
+        Extension::new(60, "jump", 1, 0, |com, args| Effect::Jump(args.read(com, 0) as usize))
+    }
+
+    #[test]
+    fn registers_by_code() {
+        let mut com = Computer::init(&[99], None);
+        com.register(jump());
+        assert_eq!(com.extension(60).map(|ext| (ext.name.as_str(), ext.width())), Some(("jump", 2)));
+        assert!(com.extension(61).is_none());
+        let mut other = Computer::init(&[99], None);
+        other.copy_extensions(&com);
+        assert!(other.extension(60).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "standard Intcode instruction")]
+    fn standard_codes_cant_be_replaced() {
+        Computer::init(&[99], None).register(Extension::new(5, "jnz", 2, 0, |_, _| Effect::Next));
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit in two digits")]
+    fn codes_have_two_digits() {
+        Computer::init(&[99], None).register(Extension::new(100, "big", 0, 0, |_, _| Effect::Next));
+    }
+
+    #[test]
+    fn jumps_are_tracked_as_calls() {
+        // rb = 20; rb[0] = 8; jump 10; halt at 8; the function at 10 outputs 7 and returns through rb[0]
+        let mut com = Computer::init(&[109, 20, 21101, 8, 0, 0, 160, 10, 99, 0, 104, 7, 2106, 0, 0], None);
+        com.register(jump());
+        com.track_calls();
+        for _ in 0..3 {
+            com.step();
+        }
+        assert_eq!((com.ptr, com.current_function()), (10, Some(10)));
+        com.compute();
+        assert!(com.is_done);
+        assert_eq!(com.call_stack().map(|stack| stack.depth()), Some(0));
+        assert_eq!(com.recv_all().collect::<Vec<_>>(), vec![7]);
+    }
+
+    #[test]
+    fn halts_like_a_halt() {
+        // out 1; stop; out 2
+        let mut com = Computer::init(&[104, 1, 61, 104, 2], None);
+        com.register(Extension::new(61, "stop", 0, 0, |_, _| Effect::Halt));
+        com.record();
+        com.compute();
+        assert!(com.is_done);
+        assert_eq!((com.ptr, com.steps()), (2, 2));
+        assert_eq!(com.session().unwrap().events, vec![Event::Output { step: 0, val: 1 }, Event::Halt { step: 1 }]);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::intcode::Computer;
+use crate::intcode::calls::CallStack;
+use crate::intcode::decode::{disassemble, Param};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Error};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Something that stops a run short of halting or waiting for input
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Crash {
+    InvalidOpcode { ptr: usize, instr: i64 },
+    InvalidMode { ptr: usize, instr: i64 },
+    /// `ptr`, reached from `from`, is outside memory or the instruction there runs off the end of it
+    OutOfBounds { ptr: usize, from: usize },
+    NegativeAddress { ptr: usize, addr: i64 },
+    RunawayMemory { ptr: usize, index: usize },
+    StepLimit { steps: u64 },
+    /// the VM itself panicked, e.g. on overflow in a debug build
+    Panic { ptr: usize, msg: String },
+}
+
+impl Crash {
+    /// Crashes with the same key are the same bug
+    fn key(&self) -> (u8, usize) {
+        match *self {
+            Crash::InvalidOpcode { ptr, .. } => (0, ptr),
+            Crash::InvalidMode { ptr, .. } => (1, ptr),
+            Crash::OutOfBounds { from, .. } => (2, from),
+            Crash::NegativeAddress { ptr, .. } => (3, ptr),
+            Crash::RunawayMemory { ptr, .. } => (4, ptr),
+            Crash::StepLimit { .. } => (5, 0),
+            Crash::Panic { ptr, .. } => (6, ptr),
+        }
+    }
+}
+
+impl Display for Crash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Crash::InvalidOpcode { ptr, instr } => write!(f, "invalid opcode {} at {}", instr, ptr),
+            Crash::InvalidMode { ptr, instr } => write!(f, "invalid parameter mode in {} at {}", instr, ptr),
+            Crash::OutOfBounds { ptr, from } => write!(f, "executing outside memory at {}, coming from {}", ptr, from),
+            Crash::NegativeAddress { ptr, addr } => write!(f, "instruction at {} uses negative address {}", ptr, addr),
+            Crash::RunawayMemory { ptr, index } => write!(f, "instruction at {} writes to {}, past the memory limit", ptr, index),
+            Crash::StepLimit { steps } => write!(f, "still running after {} steps", steps),
+            Crash::Panic { ptr, msg } => write!(f, "VM panicked at {}: {}", ptr, msg),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Limits {
+    pub steps: u64,
+    pub mem: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { steps: 1_000_000, mem: 1 << 20 }
+    }
+}
+
+impl Computer {
+    /// Whether the next step can run without tripping over undefined behaviour in the VM
+    pub(super) fn check_step(&self, from: usize, limit: usize) -> Result<(), Crash> {
+        let ptr = self.ptr;
+        let instr = *self.mem.get(ptr).ok_or(Crash::OutOfBounds { ptr, from })?;
+        let (nparams, write) = match instr % 100 {
+            1 | 2 | 7 | 8 => (3, true),
+            3 => (1, true),
+            4 | 9 => (1, false),
+            5 | 6 => (2, false),
+            99 => (0, false),
+            code if code > 0 && self.extensions.contains_key(&code) => return Ok(()),
+            _ => return Err(Crash::InvalidOpcode { ptr, instr }),
+        };
+        if ptr + nparams >= self.mem.len() {
+            return Err(Crash::OutOfBounds { ptr, from });
+        }
+        for offset in 1..=nparams {
+            let n = self.mem[ptr + offset];
+            let addr = match (instr / 10_i64.pow(offset as u32 + 1)) % 10 {
+                0 => n,
+                1 => continue,
+                2 => n.wrapping_add(self.rel_base),
+                _ => return Err(Crash::InvalidMode { ptr, instr }),
+            };
+            if addr < 0 {
+                return Err(Crash::NegativeAddress { ptr, addr });
+            }
+            if write && offset == nparams && addr as usize >= limit {
+                return Err(Crash::RunawayMemory { ptr, index: addr as usize });
+            }
+        }
+        Ok(())
+    }
+
+    /// `step`, unless that would trip over undefined behaviour, go past `limits` or panic. `from` is
+    /// where the previous instruction was, for reporting jumps out of memory. A panic still goes through
+    /// the panic hook, which is the program's to set, e.g. once at startup if it expects many.
+    pub fn try_step(&mut self, from: usize, limits: Limits) -> Result<bool, Crash> {
+        if self.steps >= limits.steps {
+            return Err(Crash::StepLimit { steps: self.steps });
+        }
+        self.check_step(from, limits.mem)?;
+        let ptr = self.ptr;
+        panic::catch_unwind(AssertUnwindSafe(|| self.step())).map_err(|err| {
+            let msg = err.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| err.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Crash::Panic { ptr, msg }
+        })
+    }
+}
+
+/// How one run ended, with the addresses it executed and the calls it was in at the end
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub covered: Vec<usize>,
+    pub crash: Option<Crash>,
+    pub stack: CallStack,
+}
+
+/// Runs `image` on `input` until it halts, wants more input, or crashes
+pub fn execute(image: &[i64], input: &[i64], limits: Limits) -> Run {
+    let mut com = Computer::init(image, input.iter().copied());
+    com.track_calls();
+    let mut seen = vec![false; image.len()];
+    let mut covered = Vec::new();
+    let mut from = 0;
+    let crash = loop {
+        if com.steps >= limits.steps {
+            break Some(Crash::StepLimit { steps: com.steps });
+        }
+        if let Err(crash) = com.check_step(from, limits.mem) {
+            break Some(crash);
+        }
+        let ptr = com.ptr;
+        from = ptr;
+        if ptr >= seen.len() {
+            seen.resize(ptr + 1, false);
+        }
+        if !seen[ptr] {
+            seen[ptr] = true;
+            covered.push(ptr);
+        }
+        match panic::catch_unwind(AssertUnwindSafe(|| com.step())) {
+            Ok(true) => {}
+            Ok(false) => break None,
+            Err(err) => {
+                let msg = err.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| err.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                break Some(Crash::Panic { ptr, msg });
+            }
+        }
+    };
+    covered.sort_unstable();
+    let stack = com.stop_tracking_calls().unwrap_or_default();
+    Run { covered, crash, stack }
+}
+
+/// Constants the program compares against or computes with, which make good inputs
+pub(super) fn dictionary(image: &[i64]) -> Vec<i64> {
+    let mut dict: Vec<i64> = vec![0, 1, -1, 2, 10, 100, i64::MAX, i64::MIN];
+    dict.extend(disassemble(image).iter()
+        .flat_map(|instr| instr.params().to_vec())
+        .filter_map(|param| match param {
+            Param::Imm(n) => Some(n),
+            _ => None,
+        }));
+    dict.sort_unstable();
+    dict.dedup();
+    dict
+}
+
+/// Mutates input sequences, keeping the ones that execute an address nothing before them has
+pub struct Fuzzer {
+    image: Vec<i64>,
+    limits: Limits,
+    rng: u64,
+    dict: Vec<i64>,
+    corpus: Vec<Vec<i64>>,
+    seen: Vec<bool>,
+    crashes: HashMap<(u8, usize), (Vec<i64>, Crash, CallStack)>,
+    runs: usize,
+}
+
+impl Fuzzer {
+    pub fn new(image: &[i64]) -> Self {
+        Fuzzer {
+            image: image.to_vec(),
+            limits: Limits::default(),
+            rng: 0x2545_f491_4f6c_dd1d,
+            dict: dictionary(image),
+            corpus: vec![Vec::new()],
+            seen: Vec::new(),
+            crashes: HashMap::new(),
+            runs: 0,
+        }
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn rng_seed(mut self, seed: u64) -> Self {
+        self.rng = seed.max(1);
+        self
+    }
+
+    /// Adds an input to start mutating from
+    pub fn seed(mut self, input: Vec<i64>) -> Self {
+        self.corpus.push(input);
+        self
+    }
+
+    pub fn corpus(&self) -> &[Vec<i64>] {
+        &self.corpus
+    }
+
+    /// Each distinct crash with the smallest input found for it, and the calls it happened in
+    pub fn crashes(&self) -> Vec<&(Vec<i64>, Crash, CallStack)> {
+        let mut crashes: Vec<_> = self.crashes.values().collect();
+        crashes.sort_by_key(|(_, crash, _)| crash.key());
+        crashes
+    }
+
+    pub fn covered(&self) -> Vec<usize> {
+        (0..self.seen.len()).filter(|&addr| self.seen[addr]).collect()
+    }
+
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    /// Runs the seeds, then `iterations` mutated inputs
+    pub fn fuzz(&mut self, iterations: usize) {
+        for input in std::mem::take(&mut self.corpus) {
+            self.try_input(input);
+        }
+        if self.corpus.is_empty() {
+            self.corpus.push(Vec::new());
+        }
+        for _ in 0..iterations {
+            let parent = self.next(self.corpus.len());
+            let parent = self.corpus[parent].clone();
+            let input = self.mutate(parent);
+            self.try_input(input);
+        }
+    }
+
+    fn run(&mut self, input: &[i64]) -> Run {
+        self.runs += 1;
+        execute(&self.image, input, self.limits)
+    }
+
+    fn try_input(&mut self, input: Vec<i64>) {
+        let run = self.run(&input);
+        let new: Vec<usize> = run.covered.iter().copied().filter(|&addr| !self.seen.get(addr).copied().unwrap_or(false)).collect();
+        if let Some(first) = run.crash {
+            let key = first.key();
+            if self.crashes.get(&key).is_none_or(|(old, _, _)| input.len() < old.len()) {
+                let input = self.minimise(input, |run| run.crash.as_ref().map(Crash::key) == Some(key));
+                // the smaller input crashes the same way, but maybe from somewhere else
+                let Run { crash, stack, .. } = self.run(&input);
+                self.crashes.insert(key, (input, crash.unwrap_or(first), stack));
+            }
+        } else if !new.is_empty() || self.corpus.is_empty() {
+            let input = self.minimise(input, |run| new.iter().all(|addr| run.covered.binary_search(addr).is_ok()));
+            self.corpus.push(input);
+        }
+        for addr in new {
+            if addr >= self.seen.len() {
+                self.seen.resize(addr + 1, false);
+            }
+            self.seen[addr] = true;
+        }
+    }
+
+    /// Drops chunks of input, then single values, then zeroes values, as long as `keep` still holds
+    fn minimise<F: Fn(&Run) -> bool>(&mut self, mut input: Vec<i64>, keep: F) -> Vec<i64> {
+        let mut chunk = input.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start < input.len() {
+                let end = (start + chunk).min(input.len());
+                let candidate: Vec<i64> = input[..start].iter().chain(&input[end..]).copied().collect();
+                if keep(&self.run(&candidate)) {
+                    input = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        for i in 0..input.len() {
+            if input[i] != 0 {
+                let mut candidate = input.clone();
+                candidate[i] = 0;
+                if keep(&self.run(&candidate)) {
+                    input = candidate;
+                }
+            }
+        }
+        input
+    }
+
+    fn mutate(&mut self, mut input: Vec<i64>) -> Vec<i64> {
+        for _ in 0..=self.next(4) {
+            let val = match self.next(3) {
+                0 => {
+                    let i = self.next(self.dict.len());
+                    self.dict[i]
+                }
+                1 => self.next(256) as i64 - 128,
+                _ => (self.rng as i64) >> self.next(64),
+            };
+            let len = input.len();
+            match self.next(6) {
+                0 | 1 => input.push(val),
+                2 if len > 0 => {
+                    let i = self.next(len);
+                    input[i] = val;
+                }
+                3 if len > 0 => {
+                    let i = self.next(len);
+                    input[i] = input[i].wrapping_add(self.next(5) as i64 - 2);
+                }
+                4 if len > 0 => {
+                    input.remove(self.next(len));
+                }
+                5 if len > 0 => {
+                    let i = self.next(len);
+                    let copy = input[i..].to_vec();
+                    input.extend(copy);
+                }
+                _ => input.insert(self.next(len + 1), val),
+            }
+        }
+        input
+    }
+
+    /// xorshift, good enough to pick mutations
+    fn next(&mut self, below: usize) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % below.max(1) as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{execute, Crash, Fuzzer, Limits};
+    use crate::intcode::Computer;
+
+    // in [20]; eq [20] 42 -> [21]; jnz [21] 10; halt; 77
+    const PROG: [i64; 11] = [3, 20, 1008, 20, 42, 21, 1005, 21, 10, 99, 77];
+
+    #[test]
+    fn execute_reports_crashes() {
+        assert_eq!(execute(&PROG, &[1], Limits::default()).crash, None);
+        let run = execute(&PROG, &[42], Limits::default());
+        assert_eq!(run.crash, Some(Crash::InvalidOpcode { ptr: 10, instr: 77 }));
+        assert_eq!(run.covered, vec![0, 2, 6]);
+        let limits = Limits { steps: 2, ..Limits::default() };
+        assert_eq!(execute(&PROG, &[1], limits).crash, Some(Crash::StepLimit { steps: 2 }));
+    }
+
+    #[test]
+    fn try_step_stops_short_of_crashes() {
+        let mut com = Computer::init(&PROG, vec![42]);
+        assert_eq!(com.try_step(0, Limits::default()), Ok(true));
+        assert_eq!(com.try_step(0, Limits { steps: 1, ..Limits::default() }), Err(Crash::StepLimit { steps: 1 }));
+        assert_eq!(com.try_step(0, Limits::default()), Ok(true));
+        assert_eq!(com.try_step(2, Limits::default()), Ok(true));
+        assert_eq!(com.try_step(6, Limits::default()), Err(Crash::InvalidOpcode { ptr: 10, instr: 77 }));
+        assert_eq!(com.ptr, 10);
+    }
+
+    #[test]
+    fn finds_and_minimises_a_crash() {
+        let mut fuzzer = Fuzzer::new(&PROG).rng_seed(1).seed(vec![0, 5, 9]);
+        fuzzer.fuzz(2_000);
+        let crashes = fuzzer.crashes();
+        assert_eq!(crashes.len(), 1);
+        assert_eq!(crashes[0].0, vec![42]);
+        assert_eq!(fuzzer.covered(), vec![0, 2, 6, 9]);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::intcode::Computer;
+use std::collections::BTreeMap;
+
+/// How many times each address was executed, read and written
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub exec: Vec<u64>,
+    pub read: Vec<u64>,
+    pub write: Vec<u64>,
+    /// instructions run in each function, not counting the functions it calls. Only counted while
+    /// calls are tracked.
+    pub functions: BTreeMap<usize, u64>,
+}
+
+fn bump(counts: &mut Vec<u64>, index: usize) {
+    if index >= counts.len() {
+        counts.resize(index + 1, 0);
+    }
+    counts[index] += 1;
+}
+
+impl Usage {
+    /// The instruction at `ptr` ran inside `func`, its parameter words count as executed too
+    pub(super) fn executed(&mut self, ptr: usize, len: usize, func: Option<usize>) {
+        for index in ptr..ptr + len {
+            bump(&mut self.exec, index);
+        }
+        if let Some(func) = func {
+            *self.functions.entry(func).or_insert(0) += 1;
+        }
+    }
+
+    /// Functions by how many instructions they ran themselves, busiest first
+    pub fn busiest(&self) -> Vec<(usize, u64)> {
+        let mut funcs: Vec<(usize, u64)> = self.functions.iter().map(|(&func, &n)| (func, n)).collect();
+        funcs.sort_by_key(|&(func, n)| (std::cmp::Reverse(n), func));
+        funcs
+    }
+
+    pub(super) fn read(&mut self, index: usize) {
+        bump(&mut self.read, index);
+    }
+
+    pub(super) fn written(&mut self, index: usize) {
+        bump(&mut self.write, index);
+    }
+
+    pub fn len(&self) -> usize {
+        self.exec.len().max(self.read.len()).max(self.write.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// One row per `width` addresses, `scale` pixels square per address. Writes are red, reads green
+    /// and execution blue, each on a log scale up to that channel's busiest address.
+    pub fn heatmap(&self, width: usize, scale: usize) -> Heatmap {
+        let (width, scale) = (width.max(1), scale.max(1));
+        let rows = self.len().max(1).div_ceil(width);
+        let channels = [&self.write, &self.read, &self.exec];
+        let max: Vec<f64> = channels.iter()
+            .map(|counts| ((counts.iter().copied().max().unwrap_or(0) + 1) as f64).ln())
+            .collect();
+        let shade = |channel: usize, index: usize| match channels[channel].get(index) {
+            Some(&n) if n > 0 => (64.0 + 191.0 * ((n + 1) as f64).ln() / max[channel]) as u8,
+            _ => 0,
+        };
+
+        let (w, h) = (width * scale, rows * scale);
+        let mut rgb = Vec::with_capacity(w * h * 3);
+        for row in 0..rows {
+            let mut line = Vec::with_capacity(w * 3);
+            for col in 0..width {
+                let index = row * width + col;
+                let px = [shade(0, index), shade(1, index), shade(2, index)];
+                for _ in 0..scale {
+                    line.extend_from_slice(&px);
+                }
+            }
+            for _ in 0..scale {
+                rgb.extend_from_slice(&line);
+            }
+        }
+        Heatmap { width: w, height: h, rgb }
+    }
+}
+
+/// An RGB picture of memory usage
+#[derive(Debug, Clone)]
+pub struct Heatmap {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl Heatmap {
+    /// Binary PPM (P6)
+    pub fn ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.rgb);
+        out
+    }
+
+    /// Truecolour PNG, deflated with stored blocks only so there's nothing to pull in for compression
+    pub fn png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for line in self.rgb.chunks(self.width * 3) {
+            raw.push(0);
+            raw.extend_from_slice(line);
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut out = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        chunk(&mut out, b"IHDR", &ihdr);
+        chunk(&mut out, b"IDAT", &zlib(&raw));
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 })
+    })
+}
+
+impl Computer {
+    /// Starts counting how often each address is executed, read and written. Immediate operands are
+    /// part of the instruction, so they count as executed rather than read.
+    pub fn track_usage(&mut self) {
+        self.usage = Some(Usage::default());
+    }
+
+    pub fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+
+    pub fn stop_usage(&mut self) -> Option<Usage> {
+        self.usage.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::Computer;
+    use crate::intcode::ext::{Extension, Effect};
+
+    #[test]
+    fn counts_every_kind_of_instruction() {
+        // add [9] [9] -> [10]; nop [9]; out [10]; halt
+        let mut com = Computer::init(&[1, 9, 9, 10, 50, 9, 4, 10, 99, 3], vec![]);
+        com.register(Extension::new(50, "nop", 1, 0, |_, _| Effect::Next));
+        com.track_usage();
+        com.compute();
+        let usage = com.stop_usage().unwrap();
+        assert_eq!(usage.exec, vec![1; 9]);
+        assert_eq!(usage.read[9..], [2, 1]);
+        assert_eq!(usage.write[10..], [1]);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::intcode::{Computer, reset, state};
+use crate::intcode::calls::CallStack;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone)]
+pub struct History {
+    len: usize,
+    checkpoint_every: usize,
+    step: u64,
+    // every output made while recording, so a checkpoint knows which ones came after it
+    outputs: u64,
+    entries: VecDeque<Entry>,
+    checkpoints: VecDeque<Checkpoint>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    step: u64,
+    ptr: usize,
+    rel_base: i64,
+    writes: Vec<WriteRecord>,
+    input: Option<i64>,
+    output: Option<i64>,
+    // the shadow call stack before the instruction, if calls are tracked
+    calls: Option<CallStack>,
+}
+
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    step: u64,
+    steps: u64,
+    outputs: u64,
+    mem: Vec<i64>,
+    ptr: usize,
+    rel_base: i64,
+    input: VecDeque<i64>,
+    calls: Option<CallStack>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct WriteRecord {
+    pub step: u64,
+    pub ptr: usize,
+    pub index: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+impl History {
+    pub fn new(len: usize, checkpoint_every: usize) -> Self {
+        History {
+            len: len.max(1),
+            checkpoint_every: checkpoint_every.max(1),
+            step: 0,
+            outputs: 0,
+            entries: VecDeque::new(),
+            checkpoints: VecDeque::new(),
+        }
+    }
+
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(super) fn begin(&mut self, ptr: usize, rel_base: i64, steps: u64, mem: &[i64], input: &VecDeque<i64>, calls: Option<&CallStack>) {
+        if self.step.is_multiple_of(self.checkpoint_every as u64) {
+            self.checkpoints.push_back(Checkpoint {
+                step: self.step,
+                steps,
+                outputs: self.outputs,
+                mem: mem.to_vec(),
+                ptr,
+                rel_base,
+                input: input.clone(),
+                calls: calls.cloned(),
+            });
+        }
+        self.entries.push_back(Entry {
+            step: self.step,
+            ptr,
+            rel_base,
+            writes: Vec::new(),
+            input: None,
+            output: None,
+            calls: calls.cloned(),
+        });
+        self.step += 1;
+    }
+
+    /// Forgets everything recorded, for when the program starts over
+    pub(super) fn clear(&mut self) {
+        *self = History::new(self.len, self.checkpoint_every);
+    }
+
+    pub(super) fn cancel(&mut self) {
+        self.step -= 1;
+        self.entries.pop_back();
+        if self.checkpoints.back().is_some_and(|c| c.step == self.step) {
+            self.checkpoints.pop_back();
+        }
+    }
+
+    /// The instruction from the last `begin` ran, so the oldest entry can go if there are too many.
+    /// Evicting any earlier would lose it for good if the instruction was cancelled instead.
+    pub(super) fn commit(&mut self) {
+        if self.entries.len() > self.len {
+            self.entries.pop_front();
+            let oldest = self.entries.front().map_or(self.step, |e| e.step);
+            // keep the one checkpoint at or before the oldest entry so it can still be reached
+            while self.checkpoints.len() > 1 && self.checkpoints[1].step <= oldest {
+                self.checkpoints.pop_front();
+            }
+        }
+    }
+
+    pub(super) fn write(&mut self, index: usize, old: i64, new: i64) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.writes.push(WriteRecord { step: entry.step, ptr: entry.ptr, index, old, new });
+        }
+    }
+
+    pub(super) fn input(&mut self, val: i64) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.input = Some(val);
+        }
+    }
+
+    pub(super) fn output(&mut self, val: i64) {
+        self.outputs += 1;
+        if let Some(entry) = self.entries.back_mut() {
+            entry.output = Some(val);
+        }
+    }
+
+    pub fn last_write(&self, index: usize) -> Option<WriteRecord> {
+        self.entries.iter()
+            .rev()
+            .flat_map(|entry| entry.writes.iter().rev())
+            .find(|write| write.index == index)
+            .copied()
+    }
+
+    pub fn writes(&self, index: usize) -> impl Iterator<Item=WriteRecord> + '_ {
+        self.entries.iter()
+            .flat_map(|entry| entry.writes.iter())
+            .filter(move |write| write.index == index)
+            .copied()
+    }
+
+    pub fn trace(&self) -> impl Iterator<Item=(u64, usize)> + '_ {
+        self.entries.iter().map(|entry| (entry.step, entry.ptr))
+    }
+}
+
+impl Computer {
+    pub fn record_history(&mut self, len: usize, checkpoint_every: usize) {
+        self.history = Some(History::new(len, checkpoint_every));
+    }
+
+    pub fn stop_history(&mut self) -> Option<History> {
+        self.history.take()
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn last_write(&self, index: usize) -> Option<WriteRecord> {
+        self.history.as_ref()?.last_write(index)
+    }
+
+    /// Undoes the last instruction. Sessions, strict mode, taint and usage counts can't be undone, so
+    /// this refuses while any of them is on.
+    pub fn step_back(&mut self) -> bool {
+        if self.session.is_some() || self.strict.is_some() || self.taint.is_some() || self.usage.is_some() {
+            return false;
+        }
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return false,
+        };
+        let entry = match history.entries.pop_back() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        history.step = entry.step;
+        if history.checkpoints.len() > 1 && history.checkpoints.back().is_some_and(|c| c.step > entry.step) {
+            history.checkpoints.pop_back();
+        }
+
+        for write in entry.writes.iter().rev() {
+            state::rewrite(&mut self.fingerprint, write.index, self.mem[write.index], write.old);
+            reset::touch(&mut self.pristine, write.index);
+            self.mem[write.index] = write.old;
+        }
+        if let Some(val) = entry.input {
+            self.input.push_front(val);
+        }
+        // an output the host already recv'd can't be taken back, and if it took this one it took
+        // everything before it too
+        if entry.output.is_some() {
+            history.outputs -= 1;
+            self.output.pop_back();
+        }
+        self.ptr = entry.ptr;
+        self.rel_base = entry.rel_base;
+        if entry.calls.is_some() {
+            self.calls = entry.calls;
+        }
+        self.steps -= 1;
+        self.is_done = false;
+        true
+    }
+
+    pub fn run_back_to(&mut self, ptr: usize) -> bool {
+        while self.step_back() {
+            if self.ptr == ptr {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn rewind_to(&mut self, step: u64) -> bool {
+        if self.session.is_some() || self.strict.is_some() || self.taint.is_some() || self.usage.is_some() {
+            return false;
+        }
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return false,
+        };
+        if step > history.step {
+            return false;
+        }
+        let oldest = history.entries.front().map_or(history.step, |e| e.step);
+        if step < oldest {
+            // past the undo log, but a checkpoint may still cover it exactly
+            let checkpoint = match history.checkpoints.iter().find(|c| c.step == step) {
+                Some(checkpoint) => checkpoint.clone(),
+                None => return false,
+            };
+            history.entries.clear();
+            history.checkpoints.retain(|c| c.step <= step);
+            history.step = step;
+            self.restore(checkpoint);
+            return true;
+        }
+
+        // jump to the closest checkpoint that's still ahead of `step`, then undo the rest
+        if let Some(i) = history.checkpoints.iter().position(|c| c.step >= step && c.step >= oldest) {
+            let checkpoint = history.checkpoints[i].clone();
+            history.checkpoints.truncate(i + 1);
+            while history.entries.back().is_some_and(|e| e.step >= checkpoint.step) {
+                history.entries.pop_back();
+            }
+            history.step = checkpoint.step;
+            self.restore(checkpoint);
+        }
+        while self.history.as_ref().is_some_and(|h| h.step > step) {
+            if !self.step_back() {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        let Checkpoint { step: _, steps, outputs, mem, ptr, rel_base, input, calls } = checkpoint;
+        // outputs made since the checkpoint are the newest ones, drop whichever of them are still queued
+        if let Some(history) = &mut self.history {
+            let newer = (history.outputs - outputs) as usize;
+            self.output.truncate(self.output.len().saturating_sub(newer));
+            history.outputs = outputs;
+        }
+        self.mem = mem;
+        self.ptr = ptr;
+        self.rel_base = rel_base;
+        self.steps = steps;
+        self.input = input;
+        if calls.is_some() {
+            self.calls = calls;
+        }
+        self.is_done = false;
+        reset::touch_all(&mut self.pristine);
+        self.refresh_fingerprint();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::Computer;
+
+    // in, out, in, out, halt
+    const ECHO: [i64; 12] = [3, 11, 4, 11, 3, 11, 4, 11, 99, 0, 0, 0];
+
+    #[test]
+    fn waiting_for_input_keeps_the_oldest_entry() {
+        let mut com = Computer::init(&ECHO, vec![5]);
+        com.record_history(2, 100);
+        com.compute();
+        assert_eq!(com.history().unwrap().len(), 2);
+        assert!(com.step_back() && com.step_back());
+        assert_eq!((com.ptr, com.steps(), com.input.front()), (0, 0, Some(&5)));
+    }
+
+    #[test]
+    fn rewinding_keeps_drained_output_drained() {
+        let mut com = Computer::init(&ECHO, vec![5, 6]);
+        com.record_history(100, 1);
+        com.compute();
+        assert_eq!(com.recv_all().collect::<Vec<_>>(), vec![5, 6]);
+        assert!(com.rewind_to(3));
+        assert_eq!((com.ptr, com.steps(), com.output.len()), (6, 3, 0));
+        com.compute();
+        assert_eq!(com.recv_all().collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn stepping_back_restores_the_call_stack() {
+        // [rb] = 9, call 10, halt at 9; the function at 10 returns through [rb]
+        let mem = vec![109, 20, 21101, 9, 0, 0, 1105, 1, 10, 99, 2106, 0, 0];
+        let mut com = Computer::init(&mem, None);
+        com.record_history(100, 2);
+        com.track_calls();
+        com.compute();
+        assert_eq!(com.call_stack().unwrap().depth(), 0);
+        assert!(com.step_back() && com.step_back());
+        assert_eq!((com.ptr, com.current_function()), (10, Some(10)));
+        assert!(com.rewind_to(2));
+        assert_eq!((com.ptr, com.call_stack().unwrap().depth()), (6, 0));
+        com.step();
+        assert_eq!(com.current_function(), Some(10));
+    }
+
+    #[test]
+    fn finds_the_writes_to_an_address() {
+        let mut com = Computer::init(&ECHO, vec![5, 6]);
+        com.record_history(100, 10);
+        com.compute();
+        assert_eq!(com.history().unwrap().writes(11).map(|write| write.new).collect::<Vec<_>>(), vec![5, 6]);
+        let last = com.last_write(11).unwrap();
+        assert_eq!((last.step, last.ptr, last.old, last.new), (2, 4, 5, 6));
+        assert_eq!(com.last_write(0), None);
+    }
+
+    #[test]
+    fn runs_back_to_an_address() {
+        let mut com = Computer::init(&ECHO, vec![5, 6]);
+        com.record_history(100, 10);
+        com.compute();
+        assert!(com.run_back_to(4));
+        assert_eq!((com.steps(), com.mem[11], com.input.front(), com.output.len()), (2, 5, Some(&6), 1));
+        assert!(!com.run_back_to(8));
+        assert_eq!(com.steps(), 0);
+    }
+
+    #[test]
+    fn rewinds_past_the_undo_log_only_to_a_checkpoint() {
+        let mut com = Computer::init(&ECHO, vec![5, 6]);
+        com.record_history(2, 2);
+        com.compute();
+        assert_eq!(com.history().unwrap().len(), 2);
+        assert!(!com.rewind_to(1));
+        assert!(com.rewind_to(2));
+        assert_eq!((com.ptr, com.steps(), com.mem[11]), (4, 2, 5));
+        assert!(!com.rewind_to(0));
+    }
+
+    #[test]
+    fn stepping_back_is_refused_while_analyses_run() {
+        let mut com = Computer::init(&ECHO, vec![5]);
+        com.record_history(100, 1);
+        com.track_usage();
+        com.step();
+        assert!(!com.step_back());
+    }
+}
@@ -0,0 +1,427 @@
+use crate::intcode::decode::{Instr, Param};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Display, Formatter, Error};
+
+/// One assembled module, with its code laid out from address 0 until the linker places it.
+///
+/// ```text
+/// # doubles each input using `double` from another module
+/// export main
+/// import double, double.x, double.ret
+///
+/// main:   in [double.x]
+///         add main, 0, [double.ret]
+///         jnz 1, double
+/// ```
+///
+/// Instructions are written the way the disassembler shows them: `[x]` is a position operand, `rb[x]`
+/// relative and anything else immediate, where `x` is a number, a symbol, or a symbol plus or minus a
+/// number. `data` emits words as they are. Labels are local to their module unless it `export`s them,
+/// and a symbol from another module has to be `import`ed. Numbers used as position operands and as
+/// jump targets are addresses in the module, so they move with it.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub code: Vec<i64>,
+    pub labels: BTreeMap<String, usize>,
+    pub exports: BTreeSet<String>,
+    pub imports: BTreeSet<String>,
+    // words that hold a module address
+    relocs: Vec<usize>,
+    // words that hold a symbol's address plus an addend
+    refs: Vec<(usize, String, i64)>,
+    // the address each source line starts at, for listings
+    lines: Vec<(Option<usize>, String)>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LinkError {
+    Syntax { module: String, line: usize, msg: String },
+    DuplicateLabel { module: String, line: usize, name: String },
+    Undeclared { module: String, line: usize, name: String },
+    UnknownExport { module: String, name: String },
+    DuplicateSymbol { name: String, first: String, second: String },
+    MissingSymbol { module: String, name: String },
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            LinkError::Syntax { module, line, msg } => write!(f, "{}:{}: {}", module, line, msg),
+            LinkError::DuplicateLabel { module, line, name } => write!(f, "{}:{}: `{}` is already defined", module, line, name),
+            LinkError::Undeclared { module, line, name } => write!(f, "{}:{}: `{}` isn't defined or imported", module, line, name),
+            LinkError::UnknownExport { module, name } => write!(f, "{}: exports `{}` but never defines it", module, name),
+            LinkError::DuplicateSymbol { name, first, second } => write!(f, "`{}` is exported by both {} and {}", name, first, second),
+            LinkError::MissingSymbol { module, name } => write!(f, "{}: imports `{}` but no module exports it", module, name),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Lit(i64),
+    Sym(String, i64),
+}
+
+fn is_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn value(s: &str) -> Result<Value, String> {
+    let s = s.trim();
+    if let Ok(n) = s.parse() {
+        return Ok(Value::Lit(n));
+    }
+    let (name, addend) = match s.rfind(['+', '-']) {
+        Some(i) if i > 0 => {
+            let n: i64 = s[i + 1..].trim().parse().map_err(|_| format!("`{}` is not a number", s[i + 1..].trim()))?;
+            (s[..i].trim(), if &s[i..=i] == "-" { -n } else { n })
+        }
+        _ => (s, 0),
+    };
+    match is_ident(name) {
+        true => Ok(Value::Sym(name.to_string(), addend)),
+        false => Err(format!("expected a number or symbol, found `{}`", s)),
+    }
+}
+
+fn operand(s: &str) -> Result<(i64, Value), String> {
+    let s = s.trim();
+    if let Some(inner) = s.strip_prefix("rb[").and_then(|s| s.strip_suffix(']')) {
+        Ok((2, value(inner)?))
+    } else if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        Ok((0, value(inner)?))
+    } else {
+        Ok((1, value(s)?))
+    }
+}
+
+fn mnemonic(name: &str) -> Option<(i64, usize)> {
+    Some(match name {
+        "add" => (1, 3),
+        "mul" => (2, 3),
+        "in" => (3, 1),
+        "out" => (4, 1),
+        "jnz" => (5, 2),
+        "jz" => (6, 2),
+        "lt" => (7, 3),
+        "eq" => (8, 3),
+        "arb" => (9, 1),
+        "halt" => (99, 0),
+        _ => return None,
+    })
+}
+
+impl Module {
+    pub fn assemble(name: &str, src: &str) -> Result<Self, LinkError> {
+        let mut module = Module {
+            name: name.to_string(),
+            code: Vec::new(),
+            labels: BTreeMap::new(),
+            exports: BTreeSet::new(),
+            imports: BTreeSet::new(),
+            relocs: Vec::new(),
+            refs: Vec::new(),
+            lines: Vec::new(),
+        };
+        let mut used = Vec::new();
+        for (i, text) in src.lines().enumerate() {
+            let line = i + 1;
+            let syntax = |msg: String| LinkError::Syntax { module: name.to_string(), line, msg };
+            let start = module.code.len();
+            let mut rest = text.split('#').next().unwrap().trim();
+
+            while let Some(colon) = rest.find(':').filter(|&colon| is_ident(rest[..colon].trim())) {
+                let label = rest[..colon].trim().to_string();
+                if module.labels.insert(label.clone(), start).is_some() {
+                    return Err(LinkError::DuplicateLabel { module: name.to_string(), line, name: label });
+                }
+                rest = rest[colon + 1..].trim();
+            }
+            let (op, args) = match rest.find(char::is_whitespace) {
+                Some(space) => (&rest[..space], rest[space..].trim()),
+                None => (rest, ""),
+            };
+            let args: Vec<&str> = match args {
+                "" => Vec::new(),
+                args => args.split(',').map(str::trim).collect(),
+            };
+
+            match op {
+                "" => {}
+                "export" | "import" => for sym in args {
+                    if !is_ident(sym) {
+                        return Err(syntax(format!("`{}` is not a symbol name", sym)));
+                    }
+                    match op {
+                        "export" => module.exports.insert(sym.to_string()),
+                        _ => module.imports.insert(sym.to_string()),
+                    };
+                },
+                "data" => for arg in args {
+                    let val = value(arg).map_err(syntax)?;
+                    module.emit(val, false, line, &mut used);
+                },
+                _ => {
+                    let (code, nparams) = mnemonic(op).ok_or_else(|| syntax(format!("unknown instruction `{}`", op)))?;
+                    if args.len() != nparams {
+                        return Err(syntax(format!("`{}` takes {} operands, found {}", op, nparams, args.len())));
+                    }
+                    let operands = args.iter().map(|arg| operand(arg)).collect::<Result<Vec<_>, _>>().map_err(syntax)?;
+                    let mut params = [Param::Imm(0); 3];
+                    for (i, (mode, _)) in operands.iter().enumerate() {
+                        params[i] = match mode {
+                            0 => Param::Pos(0),
+                            1 => Param::Imm(0),
+                            _ => Param::Rel(0),
+                        };
+                    }
+                    let instr = Instr { addr: start, code, params, len: nparams + 1 };
+                    if let Some(Param::Imm(_)) = instr.write() {
+                        return Err(syntax(format!("`{}` can't write to an immediate operand", op)));
+                    }
+                    module.code.push(instr.encode()[0]);
+                    for (i, (mode, val)) in operands.into_iter().enumerate() {
+                        let address = mode == 0 || (mode == 1 && instr.is_jump() && i == 1);
+                        module.emit(val, address, line, &mut used);
+                    }
+                }
+            }
+            let addr = if module.code.len() > start { Some(start) } else { None };
+            module.lines.push((addr, text.to_string()));
+        }
+
+        for sym in &module.exports {
+            if !module.labels.contains_key(sym) {
+                return Err(LinkError::UnknownExport { module: name.to_string(), name: sym.clone() });
+            }
+        }
+        for (sym, line) in used {
+            if !module.labels.contains_key(&sym) && !module.imports.contains(&sym) {
+                return Err(LinkError::Undeclared { module: name.to_string(), line, name: sym });
+            }
+        }
+        Ok(module)
+    }
+
+    /// `address` words that are plain numbers get relocated, symbols always resolve to where they end up
+    fn emit(&mut self, val: Value, address: bool, line: usize, used: &mut Vec<(String, usize)>) {
+        let offset = self.code.len();
+        match val {
+            Value::Lit(n) => {
+                if address {
+                    self.relocs.push(offset);
+                }
+                self.code.push(n);
+            }
+            Value::Sym(sym, addend) => {
+                used.push((sym.clone(), line));
+                self.refs.push((offset, sym, addend));
+                self.code.push(0);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Symbol {
+    pub addr: usize,
+    pub module: String,
+    pub name: String,
+    pub exported: bool,
+}
+
+/// Every label in a linked program and where it ended up, sorted by address
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SymbolMap {
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolMap {
+    /// The closest symbol at or before `addr` and how far past it `addr` is
+    pub fn lookup(&self, addr: usize) -> Option<(&Symbol, usize)> {
+        let i = self.symbols.partition_point(|sym| sym.addr <= addr).checked_sub(1)?;
+        let sym = &self.symbols[i];
+        Some((sym, addr - sym.addr))
+    }
+
+    /// `name` or `name+offset` for an address
+    pub fn name_of(&self, addr: usize) -> Option<String> {
+        self.lookup(addr).map(|(sym, offset)| match offset {
+            0 => sym.name.clone(),
+            offset => format!("{}+{}", sym.name, offset),
+        })
+    }
+
+    /// Reads back the text form, one `addr module name [export]` line per symbol
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut symbols = text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut words = line.split_whitespace();
+                let addr = words.next()?.parse().ok()?;
+                let module = words.next()?.to_string();
+                let name = words.next()?.to_string();
+                let exported = words.next() == Some("export");
+                Some(Symbol { addr, module, name, exported })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        symbols.sort_by_key(|sym| sym.addr);
+        Some(SymbolMap { symbols })
+    }
+}
+
+impl Display for SymbolMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for sym in &self.symbols {
+            write!(f, "{:>5} {} {}", sym.addr, sym.module, sym.name)?;
+            if sym.exported {
+                write!(f, " export")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A program put together from modules
+#[derive(Debug, Clone)]
+pub struct Linked {
+    pub image: Vec<i64>,
+    pub symbols: SymbolMap,
+    /// every module's source with the address of each line, in the form the debug adapter reads
+    pub listing: String,
+}
+
+/// Lays the modules out one after another in the order given, so the first one holds the entry point
+/// at address 0, then fills in every address
+pub fn link(modules: &[Module]) -> Result<Linked, LinkError> {
+    let mut bases = Vec::with_capacity(modules.len());
+    let mut len = 0;
+    for module in modules {
+        bases.push(len);
+        len += module.code.len();
+    }
+
+    let mut globals: HashMap<&str, (usize, &str)> = HashMap::new();
+    for (module, &base) in modules.iter().zip(&bases) {
+        for sym in &module.exports {
+            if let Some(&(_, first)) = globals.get(sym.as_str()) {
+                return Err(LinkError::DuplicateSymbol { name: sym.clone(), first: first.to_string(), second: module.name.clone() });
+            }
+            globals.insert(sym, (base + module.labels[sym], &module.name));
+        }
+    }
+
+    let mut image = Vec::with_capacity(len);
+    let mut symbols = Vec::new();
+    let mut listing = String::new();
+    for (module, &base) in modules.iter().zip(&bases) {
+        let mut code = module.code.clone();
+        for &offset in &module.relocs {
+            code[offset] += base as i64;
+        }
+        for (offset, sym, addend) in &module.refs {
+            let addr = match module.labels.get(sym) {
+                Some(&addr) => base + addr,
+                None => globals.get(sym.as_str())
+                    .ok_or_else(|| LinkError::MissingSymbol { module: module.name.clone(), name: sym.clone() })?.0,
+            };
+            code[*offset] = addr as i64 + addend;
+        }
+        for sym in &module.imports {
+            if !module.labels.contains_key(sym) && !globals.contains_key(sym.as_str()) {
+                return Err(LinkError::MissingSymbol { module: module.name.clone(), name: sym.clone() });
+            }
+        }
+        image.extend(code);
+
+        symbols.extend(module.labels.iter().map(|(name, &addr)| Symbol {
+            addr: base + addr,
+            module: module.name.clone(),
+            name: name.clone(),
+            exported: module.exports.contains(name),
+        }));
+        listing.push_str(&format!("# {}\n", module.name));
+        for (addr, text) in &module.lines {
+            match addr {
+                Some(addr) => listing.push_str(&format!("{:>5}: {}\n", base + addr, text)),
+                None => listing.push_str(&format!("       {}\n", text)),
+            }
+        }
+    }
+    symbols.sort_by_key(|sym| sym.addr);
+    Ok(Linked { image, symbols: SymbolMap { symbols }, listing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{link, LinkError, Module, SymbolMap};
+    use crate::intcode::Computer;
+
+    const MAIN: &str = "\
+export main
+import double, double.x, double.ret
+
+main:   in [double.x]
+        add back, 0, [double.ret]
+        jnz 1, double
+back:   out [double.x]
+        halt
+";
+
+    const DOUBLE: &str = "\
+export double, double.x, double.ret
+
+double:     add [double.x], [double.x], [double.x]
+            jz 0, [double.ret]
+double.x:   data 0
+double.ret: data 0
+";
+
+    fn modules() -> Vec<Module> {
+        vec![Module::assemble("main", MAIN).unwrap(), Module::assemble("double", DOUBLE).unwrap()]
+    }
+
+    #[test]
+    fn links_and_runs() {
+        let linked = link(&modules()).unwrap();
+        let mut com = Computer::init(&linked.image, vec![21]);
+        com.compute();
+        assert!(com.is_done);
+        assert_eq!(com.recv(), Some(42));
+        assert_eq!(linked.symbols.name_of(0).as_deref(), Some("main"));
+        assert_eq!(linked.symbols.name_of(12).as_deref(), Some("double"));
+        assert_eq!(linked.symbols.name_of(13).as_deref(), Some("double+1"));
+        assert_eq!(SymbolMap::parse(&linked.symbols.to_string()), Some(linked.symbols.clone()));
+        assert!(linked.listing.contains("   12: double:"));
+    }
+
+    #[test]
+    fn relocates_addresses_but_not_data() {
+        let first = Module::assemble("first", "data 1, 2, 3").unwrap();
+        let second = Module::assemble("second", "out [4]\njnz 1, 0\ndata 4, 7").unwrap();
+        let linked = link(&[first, second]).unwrap();
+        assert_eq!(linked.image, vec![1, 2, 3, 4, 7, 1105, 1, 3, 4, 7]);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let assemble = |src| Module::assemble("m", src).unwrap_err();
+        assert_eq!(assemble("a: halt\na: halt"), LinkError::DuplicateLabel { module: "m".into(), line: 2, name: "a".into() });
+        assert_eq!(assemble("out [x]"), LinkError::Undeclared { module: "m".into(), line: 1, name: "x".into() });
+        assert_eq!(assemble("export x\nhalt"), LinkError::UnknownExport { module: "m".into(), name: "x".into() });
+        assert!(matches!(assemble("add 1, 2, 3"), LinkError::Syntax { line: 1, .. }));
+        assert!(matches!(assemble("jmp 0"), LinkError::Syntax { line: 1, .. }));
+
+        let main = Module::assemble("main", MAIN).unwrap();
+        assert_eq!(link(std::slice::from_ref(&main)).unwrap_err(), LinkError::MissingSymbol { module: "main".into(), name: "double.x".into() });
+        let mut again = Module::assemble("double", DOUBLE).unwrap();
+        again.name = "again".into();
+        let err = link(&[main, Module::assemble("double", DOUBLE).unwrap(), again]).unwrap_err();
+        assert!(matches!(err, LinkError::DuplicateSymbol { first, second, .. } if first == "double" && second == "again"));
+    }
+}
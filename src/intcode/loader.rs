@@ -0,0 +1,203 @@
+use crate::intcode::Computer;
+use std::fmt::{Display, Formatter, Error};
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+    BadNumber { line: usize, col: usize, text: String },
+    MissingNumber { line: usize, col: usize },
+    ExpectedComma { line: usize, col: usize, found: char },
+    Truncated { offset: usize },
+    Overflow { offset: usize },
+    TrailingBytes { offset: usize },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            ParseError::BadNumber { line, col, text } => write!(f, "{}:{}: `{}` is not a number", line, col, text),
+            ParseError::MissingNumber { line, col } => write!(f, "{}:{}: expected a number", line, col),
+            ParseError::ExpectedComma { line, col, found } => write!(f, "{}:{}: expected `,`, found `{}`", line, col, found),
+            ParseError::Truncated { offset } => write!(f, "byte {}: program ends in the middle of a value", offset),
+            ParseError::Overflow { offset } => write!(f, "byte {}: value doesn't fit in an i64", offset),
+            ParseError::TrailingBytes { offset } => write!(f, "byte {}: data after the last value", offset),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    /// Whether it went past the end of a line
+    fn skip_blank(&mut self) -> bool {
+        let line = self.line;
+        while let Some(c) = self.peek() {
+            match c {
+                '#' => while self.peek().is_some_and(|c| c != '\n') {
+                    self.bump();
+                },
+                c if c.is_whitespace() => { self.bump(); }
+                _ => break,
+            }
+        }
+        self.line != line
+    }
+}
+
+fn is_delim(c: char) -> bool {
+    c == ',' || c == '#' || c.is_whitespace()
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Result<u64, ParseError> {
+    let start = *offset;
+    let mut n = 0_u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*offset).ok_or(ParseError::Truncated { offset: start })?;
+        *offset += 1;
+        if shift == 63 && byte > 1 || shift > 63 {
+            return Err(ParseError::Overflow { offset: start });
+        }
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+    }
+}
+
+impl Computer {
+    /// Comma separated values over any number of lines, where a line break also separates values.
+    /// Whitespace and `#` comments are ignored and a trailing comma is allowed.
+    pub fn load(src: &str) -> Result<Vec<i64>, ParseError> {
+        let mut cursor = Cursor { chars: src.chars().peekable(), line: 1, col: 1 };
+        let mut mem = Vec::new();
+
+        loop {
+            cursor.skip_blank();
+            let (line, col) = (cursor.line, cursor.col);
+            match cursor.peek() {
+                None => return Ok(mem),
+                Some(',') => return Err(ParseError::MissingNumber { line, col }),
+                Some(_) => {}
+            }
+
+            let mut text = String::new();
+            while let Some(c) = cursor.peek().filter(|&c| !is_delim(c)) {
+                text.push(c);
+                cursor.bump();
+            }
+            match text.parse() {
+                Ok(n) => mem.push(n),
+                Err(_) => return Err(ParseError::BadNumber { line, col, text }),
+            }
+
+            let newline = cursor.skip_blank();
+            let (line, col) = (cursor.line, cursor.col);
+            match cursor.peek() {
+                None => return Ok(mem),
+                Some(',') => { cursor.bump(); }
+                Some(_) if newline => {}
+                Some(found) => return Err(ParseError::ExpectedComma { line, col, found }),
+            }
+        }
+    }
+
+    /// A varint count followed by that many zigzag varints
+    pub fn load_binary(bytes: &[u8]) -> Result<Vec<i64>, ParseError> {
+        let mut offset = 0;
+        let len = read_varint(bytes, &mut offset)? as usize;
+        // don't trust the header for the allocation, every value is at least one byte
+        let mut mem = Vec::with_capacity(len.min(bytes.len()));
+        for _ in 0..len {
+            mem.push(unzigzag(read_varint(bytes, &mut offset)?));
+        }
+        if offset != bytes.len() {
+            return Err(ParseError::TrailingBytes { offset });
+        }
+        Ok(mem)
+    }
+
+    pub fn to_binary(mem: &[i64]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(mem.len() * 2 + 4);
+        write_varint(&mut out, mem.len() as u64);
+        for &n in mem {
+            write_varint(&mut out, zigzag(n));
+        }
+        out
+    }
+
+    pub fn to_text(mem: &[i64]) -> String {
+        mem.iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParseError;
+    use crate::intcode::Computer;
+
+    #[test]
+    fn lines_and_comments() {
+        assert_eq!(Computer::load("1,2\n3"), Ok(vec![1, 2, 3]));
+        assert_eq!(Computer::load("# header\n1, 2, # two\n  -3,\n4\n"), Ok(vec![1, 2, -3, 4]));
+        assert_eq!(Computer::load(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(Computer::load("1,x,3"), Err(ParseError::BadNumber { line: 1, col: 3, text: "x".to_string() }));
+        assert_eq!(Computer::load("1,,3"), Err(ParseError::MissingNumber { line: 1, col: 3 }));
+        assert_eq!(Computer::load("1\n2 3"), Err(ParseError::ExpectedComma { line: 2, col: 3, found: '3' }));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mem = vec![0, 1, -1, 1105, i64::MAX, i64::MIN, 99];
+        assert_eq!(Computer::load_binary(&Computer::to_binary(&mem)), Ok(mem.clone()));
+        assert_eq!(Computer::load(&Computer::to_text(&mem)), Ok(mem));
+        assert_eq!(Computer::load_binary(&[2, 4]), Err(ParseError::Truncated { offset: 2 }));
+        assert_eq!(Computer::load_binary(&[1, 4, 4]), Err(ParseError::TrailingBytes { offset: 2 }));
+    }
+}
@@ -0,0 +1,356 @@
+use crate::intcode::batch::Job;
+use crate::intcode::fuzz::{Crash, Limits};
+use std::collections::BTreeMap;
+
+/// How a machine in a lockstep run ended
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Outcome {
+    pub output: Vec<i64>,
+    /// false when it stopped waiting for more input, or crashed
+    pub halted: bool,
+    pub crash: Option<Crash>,
+    /// its memory as it was left, up to the furthest address it wrote to
+    pub mem: Vec<i64>,
+}
+
+/// Runs many copies of one program together, one instruction for all machines at a time while they're
+/// at the same pointer. Memory is stored address-major, with every machine's copy of an address next to
+/// each other, so an instruction is decoded once and applied across all of them in a tight loop.
+/// Machines that take different branches split into groups, and the group at the lowest pointer always
+/// runs first. For branches that skip forward that's the one furthest behind, so the groups tend to meet
+/// up again where their paths rejoin; a machine going round a loop more times than the others keeps
+/// the rest waiting at the exit until it's done.
+///
+/// Only standard Intcode, there are no hooks, extensions or history here. A machine that goes past
+/// `limits.steps` instructions or writes past `limits.mem` crashes on its own, memory isn't grown
+/// for every machine to match.
+pub struct Lockstep {
+    image: Vec<i64>,
+    limits: Limits,
+}
+
+struct Machines {
+    lanes: usize,
+    limits: Limits,
+    mem: Vec<i64>,
+    // how much of the shared memory each machine has used
+    used: Vec<usize>,
+    rel_base: Vec<i64>,
+    steps: Vec<u64>,
+    input: Vec<(Vec<i64>, usize)>,
+    outcomes: Vec<Outcome>,
+}
+
+fn len(code: i64) -> usize {
+    match code {
+        1 | 2 | 7 | 8 => 4,
+        5 | 6 => 3,
+        3 | 4 | 9 => 2,
+        _ => 1,
+    }
+}
+
+fn modes(instr: i64) -> [i64; 3] {
+    [(instr / 100) % 10, (instr / 1000) % 10, (instr / 10000) % 10]
+}
+
+impl Machines {
+    #[inline(always)]
+    fn get(&self, index: usize, lane: usize) -> i64 {
+        self.mem.get(index.saturating_mul(self.lanes).saturating_add(lane)).copied().unwrap_or(0)
+    }
+
+    #[inline(always)]
+    fn index(&self, ptr: usize, offset: usize, m: [i64; 3], lane: usize) -> Result<usize, Crash> {
+        let n = self.get(ptr + offset, lane);
+        let addr = match m[offset - 1] {
+            0 => n,
+            1 => return Ok(ptr + offset),
+            2 => n.wrapping_add(self.rel_base[lane]),
+            _ => return Err(Crash::InvalidMode { ptr, instr: self.get(ptr, lane) }),
+        };
+        if addr < 0 {
+            return Err(Crash::NegativeAddress { ptr, addr });
+        }
+        Ok(addr as usize)
+    }
+
+    #[inline(always)]
+    fn read(&self, ptr: usize, offset: usize, m: [i64; 3], lane: usize) -> Result<i64, Crash> {
+        Ok(self.get(self.index(ptr, offset, m, lane)?, lane))
+    }
+
+    #[inline(always)]
+    fn write(&mut self, ptr: usize, offset: usize, m: [i64; 3], lane: usize, val: i64) -> Result<(), Crash> {
+        let index = self.index(ptr, offset, m, lane)?;
+        if index >= self.limits.mem {
+            return Err(Crash::RunawayMemory { ptr, index });
+        }
+        self.used[lane] = self.used[lane].max(index + 1);
+        let index = index * self.lanes + lane;
+        if index >= self.mem.len() {
+            let len = (index / self.lanes + 1) * self.lanes;
+            self.mem.resize(len, 0);
+        }
+        self.mem[index] = val;
+        Ok(())
+    }
+
+    /// One instruction for one machine, giving where it goes next unless it halted, crashed or is
+    /// waiting for input
+    #[inline(always)]
+    fn exec(&mut self, ptr: usize, code: i64, m: [i64; 3], lane: usize) -> Option<usize> {
+        let next = match self.steps[lane] {
+            steps if steps >= self.limits.steps => Err(Crash::StepLimit { steps }),
+            _ => self.try_exec(ptr, code, m, lane),
+        };
+        match next {
+            Ok(Some(next)) => {
+                self.steps[lane] += 1;
+                Some(next)
+            }
+            Ok(None) => None,
+            Err(crash) => {
+                self.outcomes[lane].crash = Some(crash);
+                None
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn try_exec(&mut self, ptr: usize, code: i64, m: [i64; 3], lane: usize) -> Result<Option<usize>, Crash> {
+        match code {
+            1 | 2 | 7 | 8 => {
+                let (a, b) = (self.read(ptr, 1, m, lane)?, self.read(ptr, 2, m, lane)?);
+                let val = match code {
+                    1 => a.wrapping_add(b),
+                    2 => a.wrapping_mul(b),
+                    7 => (a < b) as i64,
+                    _ => (a == b) as i64,
+                };
+                self.write(ptr, 3, m, lane, val)?;
+            }
+            3 => {
+                let (input, read) = &mut self.input[lane];
+                let val = match input.get(*read) {
+                    Some(&val) => val,
+                    None => return Ok(None),
+                };
+                *read += 1;
+                self.write(ptr, 1, m, lane, val)?;
+            }
+            4 => {
+                let val = self.read(ptr, 1, m, lane)?;
+                self.outcomes[lane].output.push(val);
+            }
+            5 | 6 => {
+                if (self.read(ptr, 1, m, lane)? != 0) == (code == 5) {
+                    let to = self.read(ptr, 2, m, lane)?;
+                    if to < 0 {
+                        return Err(Crash::NegativeAddress { ptr, addr: to });
+                    }
+                    // like the VM, a jump to the jump itself carries on with the next instruction
+                    if to as usize != ptr {
+                        return Ok(Some(to as usize));
+                    }
+                }
+            }
+            9 => self.rel_base[lane] = self.rel_base[lane].wrapping_add(self.read(ptr, 1, m, lane)?),
+            99 => {
+                self.outcomes[lane].halted = true;
+                return Ok(None);
+            }
+            _ => return Err(Crash::InvalidOpcode { ptr, instr: self.get(ptr, lane) }),
+        }
+        Ok(Some(ptr + len(code)))
+    }
+
+    /// Runs `lanes` together from `ptr` until they might go different ways, leaving where each one that's
+    /// still running goes next in `next`
+    fn run_group(&mut self, mut ptr: usize, lanes: &[usize], next: &mut Vec<(usize, usize)>) {
+        loop {
+            let instr = self.get(ptr, lanes[0]);
+            let code = instr % 100;
+            // self-modifying code can leave the machines with different instructions here
+            let same = lanes.iter().all(|&lane| self.get(ptr, lane) == instr);
+            let straight = same && match code {
+                1 | 2 | 4 | 7 | 8 | 9 => true,
+                3 => lanes.iter().all(|&lane| self.input[lane].1 < self.input[lane].0.len()),
+                _ => false,
+            };
+            if !straight {
+                for &lane in lanes {
+                    let instr = self.get(ptr, lane);
+                    if let Some(to) = self.exec(ptr, instr % 100, modes(instr), lane) {
+                        next.push((to, lane));
+                    }
+                }
+                return;
+            }
+            let m = modes(instr);
+            let mut crashed = false;
+            for &lane in lanes {
+                crashed |= self.exec(ptr, code, m, lane).is_none();
+            }
+            ptr += len(code);
+            if crashed {
+                // the rest carry on as a smaller group
+                let to = ptr;
+                next.extend(lanes.iter().filter(|&&lane| self.outcomes[lane].crash.is_none()).map(|&lane| (to, lane)));
+                return;
+            }
+        }
+    }
+}
+
+impl Lockstep {
+    pub fn new(image: &[i64]) -> Self {
+        Lockstep {
+            image: image.to_vec(),
+            limits: Limits::default(),
+        }
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Runs every job until it halts or runs out of input, results come back in job order
+    pub fn run(&self, jobs: &[Job]) -> Vec<Outcome> {
+        let lanes = jobs.len();
+        if lanes == 0 {
+            return Vec::new();
+        }
+        let mut mem = Vec::with_capacity(self.image.len() * lanes);
+        for &val in &self.image {
+            mem.extend((0..lanes).map(|_| val));
+        }
+        let mut machines = Machines {
+            lanes,
+            limits: self.limits,
+            mem,
+            used: vec![self.image.len(); lanes],
+            rel_base: vec![0; lanes],
+            steps: vec![0; lanes],
+            input: jobs.iter().map(|(_, input)| (input.clone(), 0)).collect(),
+            outcomes: vec![Outcome { output: Vec::new(), halted: false, crash: None, mem: Vec::new() }; lanes],
+        };
+        for (lane, (patches, _)) in jobs.iter().enumerate() {
+            for &(index, val) in patches {
+                machines.used[lane] = machines.used[lane].max(index + 1);
+                let index = index * lanes + lane;
+                if index >= machines.mem.len() {
+                    machines.mem.resize((index / lanes + 1) * lanes, 0);
+                }
+                machines.mem[index] = val;
+            }
+        }
+
+        let mut groups = BTreeMap::new();
+        groups.insert(0, (0..lanes).collect::<Vec<_>>());
+        let mut next = Vec::new();
+        while let Some(&ptr) = groups.keys().next() {
+            let group = groups.remove(&ptr).unwrap();
+            machines.run_group(ptr, &group, &mut next);
+            for (to, lane) in next.drain(..) {
+                groups.entry(to).or_insert_with(Vec::new).push(lane);
+            }
+        }
+        let Machines { mem, used, mut outcomes, .. } = machines;
+        for (lane, outcome) in outcomes.iter_mut().enumerate() {
+            outcome.mem = mem.iter().skip(lane).step_by(lanes).take(used[lane]).copied().collect();
+        }
+        outcomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lockstep;
+    use crate::intcode::Computer;
+    use crate::intcode::fuzz::{Crash, Limits};
+
+    #[test]
+    fn matches_separate_computers() {
+        let image = Computer::load(include_str!("../../input/2019/day5.txt")).unwrap();
+        let jobs = vec![(vec![], vec![1]), (vec![], vec![5]), (vec![], vec![])];
+        let outcomes = Lockstep::new(&image).run(&jobs);
+        for ((_, input), outcome) in jobs.iter().zip(&outcomes) {
+            let mut com = Computer::init(&image, input.iter().copied());
+            com.compute();
+            assert_eq!(outcome.output, com.output.iter().copied().collect::<Vec<_>>());
+            assert_eq!(outcome.halted, com.is_done);
+            assert_eq!(outcome.crash, None);
+        }
+    }
+
+    #[test]
+    fn exposes_memory() {
+        // add [1] [2] -> [0]; halt
+        let jobs = vec![(vec![(1, 5), (2, 6)], vec![]), (vec![(1, 6), (2, 6)], vec![])];
+        let outcomes = Lockstep::new(&[1, 0, 0, 0, 99, 3, 4]).run(&jobs);
+        assert_eq!(outcomes[0].mem, vec![7, 5, 6, 0, 99, 3, 4]);
+        assert_eq!(outcomes[1].mem, vec![8, 6, 6, 0, 99, 3, 4]);
+    }
+
+    #[test]
+    fn crashes_stay_in_their_lane() {
+        // in [6]; add 0 0 -> [10]; then the input runs as an instruction, with 0 and a halt after it
+        let image = [3, 6, 1101, 0, 0, 10, 0, 0, 99];
+        let jobs: Vec<_> = vec![99, 77, 304, 104, 4].into_iter().map(|n| (vec![], vec![n])).collect();
+        let outcomes = Lockstep::new(&image).run(&jobs);
+        let ended: Vec<_> = outcomes.iter().map(|outcome| (outcome.output.clone(), outcome.halted, outcome.crash.clone())).collect();
+        assert_eq!(ended, vec![
+            (vec![], true, None),
+            (vec![], false, Some(Crash::InvalidOpcode { ptr: 6, instr: 77 })),
+            (vec![], false, Some(Crash::InvalidMode { ptr: 6, instr: 304 })),
+            (vec![0], true, None),
+            (vec![3], true, None),
+        ]);
+
+        // in [20]; add rel[-30] 0 -> [21]; out 1; halt
+        let image = [3, 20, 9, 20, 1201, -30, 0, 21, 104, 1, 99];
+        let jobs = vec![(vec![], vec![0]), (vec![], vec![40])];
+        let outcomes = Lockstep::new(&image).run(&jobs);
+        assert_eq!(outcomes[0].crash, Some(Crash::NegativeAddress { ptr: 4, addr: -30 }));
+        assert_eq!((outcomes[1].crash.clone(), outcomes[1].output.clone(), outcomes[1].halted), (None, vec![1], true));
+    }
+
+    #[test]
+    fn jumps_to_themselves_move_on() {
+        let outcomes = Lockstep::new(&[1105, 1, 0, 1106, 0, 3, 104, 5, 99]).run(&[(vec![], vec![])]);
+        assert_eq!((outcomes[0].output.clone(), outcomes[0].halted), (vec![5], true));
+    }
+
+    #[test]
+    fn each_machine_has_its_own_step_budget() {
+        // in [20]; loops while it's non-zero, otherwise outputs 7 and halts
+        let image = [3, 20, 1006, 20, 8, 1105, 1, 2, 104, 7, 99];
+        let jobs = vec![(vec![], vec![0]), (vec![], vec![1])];
+        let outcomes = Lockstep::new(&image).limits(Limits { steps: 1000, ..Limits::default() }).run(&jobs);
+        assert_eq!((outcomes[0].output.clone(), outcomes[0].halted, outcomes[0].crash.clone()), (vec![7], true, None));
+        assert_eq!((outcomes[1].halted, outcomes[1].crash.clone()), (false, Some(Crash::StepLimit { steps: 1000 })));
+    }
+
+    #[test]
+    fn arithmetic_wraps() {
+        let image = [1101, i64::MAX, 1, 0, 4, 0, 1102, i64::MAX, 2, 0, 4, 0, 99];
+        let outcomes = Lockstep::new(&image).run(&[(vec![], vec![])]);
+        assert_eq!(outcomes[0].output, vec![i64::MIN, -2]);
+    }
+
+    #[test]
+    fn memory_ends_where_each_machine_left_it() {
+        // in [5], which is where the add writes 2 to
+        let image = [3, 5, 1101, 1, 1, 0, 99];
+        let jobs = vec![(vec![], vec![50]), (vec![], vec![1]), (vec![(9, 4)], vec![1])];
+        let outcomes = Lockstep::new(&image).run(&jobs);
+        let mut far = vec![3, 5, 1101, 1, 1, 50, 99];
+        far.resize(50, 0);
+        far.push(2);
+        assert_eq!(outcomes[0].mem, far);
+        assert_eq!(outcomes[1].mem, vec![3, 2, 1101, 1, 1, 1, 99]);
+        assert_eq!(outcomes[2].mem, vec![3, 2, 1101, 1, 1, 1, 99, 0, 0, 4]);
+    }
+}
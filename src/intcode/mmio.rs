@@ -0,0 +1,163 @@
+use crate::intcode::Computer;
+use std::fmt::{Debug, Formatter, Error};
+use std::ops::Range;
+
+type OnRead = Box<dyn FnMut(usize, i64) -> i64 + Send>;
+type OnWrite = Box<dyn FnMut(usize, i64, i64) -> Option<i64> + Send>;
+
+pub struct Region {
+    range: Range<usize>,
+    on_read: Option<OnRead>,
+    on_write: Option<OnWrite>,
+}
+
+impl Region {
+    pub fn new(range: Range<usize>) -> Self {
+        Region {
+            range,
+            on_read: None,
+            on_write: None,
+        }
+    }
+
+    /// `f(index, stored)` returns the value the program sees instead of `stored`
+    pub fn on_read<F>(mut self, f: F) -> Self
+        where F: FnMut(usize, i64) -> i64 + Send + 'static {
+        self.on_read = Some(Box::new(f));
+        self
+    }
+
+    /// `f(index, old, new)` returns the value to actually store, or `None` to drop the write
+    pub fn on_write<F>(mut self, f: F) -> Self
+        where F: FnMut(usize, i64, i64) -> Option<i64> + Send + 'static {
+        self.on_write = Some(Box::new(f));
+        self
+    }
+
+    pub fn read_only(range: Range<usize>) -> Self {
+        Region::new(range).on_write(|_, _, _| None)
+    }
+
+    pub fn watch<F>(range: Range<usize>, mut f: F) -> Self
+        where F: FnMut(usize, i64, i64) + Send + 'static {
+        Region::new(range).on_write(move |index, old, new| {
+            if old != new {
+                f(index, old, new);
+            }
+            Some(new)
+        })
+    }
+
+    pub fn range(&self) -> &Range<usize> {
+        &self.range
+    }
+}
+
+impl Debug for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.debug_struct("Region")
+            .field("range", &self.range)
+            .field("on_read", &self.on_read.is_some())
+            .field("on_write", &self.on_write.is_some())
+            .finish()
+    }
+}
+
+impl Computer {
+    pub fn map_region(&mut self, region: Region) {
+        self.regions.push(region);
+    }
+
+    pub fn unmap_regions(&mut self, index: usize) -> Vec<Region> {
+        let (unmapped, kept) = self.regions.drain(..)
+            .partition(|region| region.range.contains(&index));
+        self.regions = kept;
+        unmapped
+    }
+
+    pub(super) fn read_mapped(&mut self, index: usize, val: i64) -> i64 {
+        match self.regions.iter_mut()
+            .find(|region| region.range.contains(&index))
+            .and_then(|region| region.on_read.as_mut()) {
+            Some(on_read) => on_read(index, val),
+            None => val,
+        }
+    }
+
+    pub(super) fn write_mapped(&mut self, index: usize, val: i64) -> Option<i64> {
+        let old = *self.mem.get(index).unwrap_or(&0);
+        match self.regions.iter_mut()
+            .find(|region| region.range.contains(&index))
+            .and_then(|region| region.on_write.as_mut()) {
+            Some(on_write) => on_write(index, old, val),
+            None => Some(val),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Region;
+    use crate::intcode::Computer;
+    use std::sync::{Arc, Mutex};
+
+    fn outputs(com: &mut Computer) -> Vec<i64> {
+        com.compute();
+        com.recv_all().collect()
+    }
+
+    #[test]
+    fn reads_go_through_on_read() {
+        // out [5]; out [6]; halt; 1; 2
+        let mut com = Computer::init(&[4, 5, 4, 6, 99, 1, 2], None);
+        com.map_region(Region::new(5..6).on_read(|index, stored| stored + 100 * index as i64));
+        assert_eq!(outputs(&mut com), vec![501, 2]);
+    }
+
+    #[test]
+    fn writes_go_through_on_write() {
+        // add 3 4 -> [20]; add 3 4 -> [21]; out [20]; out [21]; halt
+        let prog = [1101, 3, 4, 20, 1101, 3, 4, 21, 4, 20, 4, 21, 99];
+        let mut com = Computer::init(&prog, None);
+        com.map_region(Region::new(20..21).on_write(|_, _, new| Some(new * 2)));
+        com.map_region(Region::new(21..22).on_write(|_, _, _| None));
+        assert_eq!(outputs(&mut com), vec![14, 0]);
+    }
+
+    #[test]
+    fn read_only_code_stays_put() {
+        // add 5 5 -> [0]; out [0]; halt
+        let mut com = Computer::init(&[1101, 5, 5, 0, 4, 0, 99], None);
+        com.map_region(Region::read_only(0..7));
+        assert_eq!(outputs(&mut com), vec![1101]);
+    }
+
+    #[test]
+    fn watches_report_changes_only() {
+        // add 0 7 -> [20]; add 0 7 -> [20]; add 0 8 -> [20]; halt
+        let prog = [1101, 0, 7, 20, 1101, 0, 7, 20, 1101, 0, 8, 20, 99];
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let mut com = Computer::init(&prog, None);
+        com.map_region(Region::watch(20..21, move |index, old, new| log.lock().unwrap().push((index, old, new))));
+        com.compute();
+        assert_eq!(*seen.lock().unwrap(), vec![(20, 0, 7), (20, 7, 8)]);
+    }
+
+    #[test]
+    fn the_first_overlapping_region_wins_until_unmapped() {
+        let mapped = || {
+            // out [3]; halt; 1
+            let mut com = Computer::init(&[4, 3, 99, 1], None);
+            com.map_region(Region::new(0..4).on_read(|_, _| 10));
+            com.map_region(Region::new(3..5).on_read(|_, _| 20));
+            com.map_region(Region::new(4..6).on_read(|_, _| 30));
+            com
+        };
+        assert_eq!(outputs(&mut mapped()), vec![10]);
+        let mut com = mapped();
+        let unmapped = com.unmap_regions(3);
+        assert_eq!(unmapped.iter().map(|region| region.range().clone()).collect::<Vec<_>>(), vec![0..4, 3..5]);
+        assert_eq!(outputs(&mut com), vec![1]);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::intcode::Computer;
+use std::fmt::{Debug, Formatter, Error};
+
+/// A fixed number of consecutive outputs that mean one thing together
+pub trait Record: Sized {
+    const ARITY: usize;
+
+    fn decode(vals: &[i64]) -> Self;
+}
+
+impl Record for i64 {
+    const ARITY: usize = 1;
+
+    fn decode(vals: &[i64]) -> Self {
+        vals[0]
+    }
+}
+
+impl Record for (i64, i64) {
+    const ARITY: usize = 2;
+
+    fn decode(vals: &[i64]) -> Self {
+        (vals[0], vals[1])
+    }
+}
+
+impl Record for (i64, i64, i64) {
+    const ARITY: usize = 3;
+
+    fn decode(vals: &[i64]) -> Self {
+        (vals[0], vals[1], vals[2])
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Observers(Vec<Box<dyn FnMut(i64) + Send>>);
+
+impl Observers {
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(super) fn notify(&mut self, val: i64) {
+        self.0.iter_mut().for_each(|f| f(val));
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl Computer {
+    /// `f` sees every output as it's produced, it still goes to the output queue as well
+    pub fn observe<F: FnMut(i64) + Send + 'static>(&mut self, f: F) {
+        self.observers.0.push(Box::new(f));
+    }
+
+    /// Groups outputs into records, counting from the next output
+    pub fn observe_records<R, F>(&mut self, mut f: F)
+        where R: Record, F: FnMut(R) + Send + 'static {
+        let mut buf = Vec::with_capacity(R::ARITY);
+        self.observe(move |val| {
+            buf.push(val);
+            if buf.len() == R::ARITY {
+                f(R::decode(&buf));
+                buf.clear();
+            }
+        });
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.0.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::Computer;
+    use std::iter::empty;
+    use std::sync::{Arc, Mutex};
+
+    // outputs 1 to 7, waits for an input, then outputs it
+    const COUNT: [i64; 20] = [104, 1, 104, 2, 104, 3, 104, 4, 104, 5, 104, 6, 104, 7, 3, 19, 4, 19, 99, 0];
+
+    type Seen<T> = Arc<Mutex<Vec<T>>>;
+
+    fn collect<T>() -> (Seen<T>, Seen<T>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        (seen.clone(), seen)
+    }
+
+    #[test]
+    fn observers_see_outputs_that_stay_queued() {
+        let mut com = Computer::init(&COUNT, vec![8]);
+        let (seen, hook) = collect();
+        com.observe(move |val| hook.lock().unwrap().push(val));
+        let (twice, hook) = collect();
+        com.observe(move |val| hook.lock().unwrap().push(val * 2));
+        com.compute();
+        assert_eq!(*seen.lock().unwrap(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(*twice.lock().unwrap(), vec![2, 4, 6, 8, 10, 12, 14, 16]);
+        assert_eq!(com.recv_all().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn records_group_outputs() {
+        let mut com = Computer::init(&COUNT, vec![8]);
+        let (pairs, hook) = collect();
+        com.observe_records(move |pair: (i64, i64)| hook.lock().unwrap().push(pair));
+        let (triples, hook) = collect();
+        com.observe_records(move |triple: (i64, i64, i64)| hook.lock().unwrap().push(triple));
+        com.compute();
+        assert_eq!(*pairs.lock().unwrap(), vec![(1, 2), (3, 4), (5, 6), (7, 8)]);
+        // the last two outputs don't make a whole triple
+        assert_eq!(*triples.lock().unwrap(), vec![(1, 2, 3), (4, 5, 6)]);
+    }
+
+    #[test]
+    fn records_span_waits_for_input() {
+        let mut com = Computer::init(&COUNT, empty());
+        let (pairs, hook) = collect();
+        com.observe_records(move |pair: (i64, i64)| hook.lock().unwrap().push(pair));
+        com.compute();
+        assert_eq!(*pairs.lock().unwrap(), vec![(1, 2), (3, 4), (5, 6)]);
+        com.send(8);
+        com.compute();
+        assert_eq!(*pairs.lock().unwrap(), vec![(1, 2), (3, 4), (5, 6), (7, 8)]);
+    }
+
+    #[test]
+    fn records_count_from_the_next_output() {
+        let mut com = Computer::init(&COUNT, vec![8]);
+        for _ in 0..3 {
+            com.step();
+        }
+        let (pairs, hook) = collect();
+        com.observe_records(move |pair: (i64, i64)| hook.lock().unwrap().push(pair));
+        com.compute();
+        assert_eq!(*pairs.lock().unwrap(), vec![(4, 5), (6, 7)]);
+    }
+
+    #[test]
+    fn cleared_observers_stop_seeing_outputs() {
+        let mut com = Computer::init(&COUNT, empty());
+        let (seen, hook) = collect();
+        com.observe(move |val| hook.lock().unwrap().push(val));
+        com.compute();
+        com.clear_observers();
+        com.send(8);
+        com.compute();
+        assert_eq!(*seen.lock().unwrap(), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(com.recv_all().count(), 8);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::intcode::Computer;
+use crate::intcode::decode::{discover, Instr, Param};
+use crate::intcode::fuzz::Limits;
+use crate::intcode::testcase::Stop;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter, Error};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Why a program can't be optimised without running the risk of changing what it does
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OptimizeError {
+    /// the instruction at `ptr` writes relative to the relative base, so it could write anywhere
+    RelativeWrite { ptr: usize },
+    /// the instruction at `ptr` jumps to an address it reads from memory
+    ComputedJump { ptr: usize },
+    /// execution can reach `addr`, which doesn't hold an instruction
+    UnknownCode { addr: usize },
+    /// the instruction at `ptr` rewrites the instruction words at `target`, which can run afterwards
+    SelfModifying { ptr: usize, target: usize },
+}
+
+impl Display for OptimizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            OptimizeError::RelativeWrite { ptr } => write!(f, "the instruction at {} writes to a relative address", ptr),
+            OptimizeError::ComputedJump { ptr } => write!(f, "the instruction at {} jumps to a computed address", ptr),
+            OptimizeError::UnknownCode { addr } => write!(f, "execution can reach {}, which isn't an instruction", addr),
+            OptimizeError::SelfModifying { ptr, target } => write!(f, "the instruction at {} rewrites code at {} that can run again", ptr, target),
+        }
+    }
+}
+
+impl std::error::Error for OptimizeError {}
+
+/// Runs until the program halts, wants input, crashes or runs out of steps. Input and output are left
+/// in the queues.
+fn run(com: &mut Computer, limits: Limits) -> Stop {
+    let mut from = 0;
+    loop {
+        if com.steps >= limits.steps {
+            break Stop::StepLimit;
+        }
+        if com.check_step(from, limits.mem).is_err() {
+            break Stop::Crash;
+        }
+        from = com.ptr;
+        match panic::catch_unwind(AssertUnwindSafe(|| com.step())) {
+            Ok(true) => {}
+            Ok(false) => break if com.is_done { Stop::Halt } else { Stop::Input },
+            Err(_) => break Stop::Crash,
+        }
+    }
+}
+
+/// Checks that every write and jump in `code` is known before the program runs, so the words the
+/// optimizer leaves alone are all the words that could matter
+fn check_static(code: &BTreeMap<usize, Instr>) -> Result<(), OptimizeError> {
+    for instr in code.values() {
+        if let Some(Param::Rel(_)) = instr.write() {
+            return Err(OptimizeError::RelativeWrite { ptr: instr.addr });
+        }
+        if instr.is_computed_jump() {
+            return Err(OptimizeError::ComputedJump { ptr: instr.addr });
+        }
+        if let Some(&addr) = instr.successors().iter().find(|addr| !code.contains_key(addr)) {
+            return Err(OptimizeError::UnknownCode { addr });
+        }
+    }
+    for instr in code.values() {
+        let target = match instr.write() {
+            Some(Param::Pos(target)) => target as usize,
+            _ => continue,
+        };
+        let owner = match code.range(..=target).next_back() {
+            Some((_, owner)) if target < owner.next() => owner,
+            _ => continue,
+        };
+        // a new immediate operand is just data, anything else changes what the instruction does
+        if target != owner.addr {
+            if let Param::Imm(_) = owner.params[target - owner.addr - 1] {
+                continue;
+            }
+        }
+        if reachable(code, instr).contains(&owner.addr) {
+            return Err(OptimizeError::SelfModifying { ptr: instr.addr, target });
+        }
+    }
+    Ok(())
+}
+
+/// Every instruction that can run after `from`
+fn reachable(code: &BTreeMap<usize, Instr>, from: &Instr) -> HashSet<usize> {
+    let mut seen = HashSet::new();
+    let mut todo = from.successors();
+    while let Some(addr) = todo.pop() {
+        if seen.insert(addr) {
+            todo.extend(code[&addr].successors());
+        }
+    }
+    seen
+}
+
+fn noop_target(instr: &Instr) -> bool {
+    match (instr.code, instr.params[0], instr.params[1]) {
+        (5, _, Param::Imm(target)) | (6, _, Param::Imm(target)) if target as usize == instr.next() => true,
+        (5, Param::Imm(0), _) => true,
+        (6, Param::Imm(c), _) => c != 0,
+        _ => false,
+    }
+}
+
+fn fold(instr: &Instr) -> Option<Vec<i64>> {
+    let (a, b) = match (instr.params[0], instr.params[1]) {
+        (Param::Imm(a), Param::Imm(b)) => (a, b),
+        _ => return None,
+    };
+    let res = match instr.code {
+        1 if b == 0 => return None,
+        1 => a.checked_add(b)?,
+        2 => a.checked_mul(b)?,
+        7 => (a < b) as i64,
+        8 => (a == b) as i64,
+        // an always-taken jump becomes the canonical unconditional `jnz 1, target`
+        5 if a != 0 && a != 1 => return Some(Instr { params: [Param::Imm(1), Param::Imm(b), Param::Imm(0)], ..*instr }.encode()),
+        6 if a == 0 => return Some(Instr { code: 5, params: [Param::Imm(1), Param::Imm(b), Param::Imm(0)], ..*instr }.encode()),
+        _ => return None,
+    };
+    Some(Instr { code: 1, params: [Param::Imm(res), Param::Imm(0), instr.params[2]], ..*instr }.encode())
+}
+
+/// A store that's overwritten further down the same straight-line run before anything could read it
+fn dead_store(instr: &Instr, code: &BTreeMap<usize, Instr>) -> bool {
+    let target = match (instr.code, instr.write()) {
+        (1, Some(Param::Pos(t))) | (2, Some(Param::Pos(t))) | (7, Some(Param::Pos(t))) | (8, Some(Param::Pos(t))) => t as usize,
+        _ => return false,
+    };
+    let mut addr = instr.next();
+    while let Some(next) = code.get(&addr) {
+        // anything the next instruction reads, including its own words, might be the stored value
+        if (next.addr..next.next()).contains(&target) {
+            return false;
+        }
+        for param in next.reads() {
+            match *param {
+                Param::Pos(i) if i as usize == target => return false,
+                Param::Rel(_) => return false,
+                _ => {}
+            }
+        }
+        // the host can look at memory while the program waits for input
+        if next.is_jump() || next.code == 3 || next.code == 99 {
+            return false;
+        }
+        match next.write() {
+            Some(Param::Pos(i)) if i as usize == target => return true,
+            Some(Param::Pos(_)) => {}
+            _ => return false,
+        }
+        addr = next.next();
+    }
+    false
+}
+
+/// Folds constant instructions, straightens out constant jumps and removes dead stores without
+/// moving any code. No word in `patched`, which the host may write, or used by a position mode
+/// operand is changed. Programs whose writes or jumps can't all be worked out up front are turned
+/// down, since any word could turn out to matter to them.
+pub fn optimize(mem: &[i64], patched: &HashSet<usize>) -> Result<Vec<i64>, OptimizeError> {
+    let code = discover(mem, &[0]);
+    check_static(&code)?;
+    let mut protected = patched.clone();
+    for instr in code.values() {
+        for param in instr.reads().iter().chain(instr.write().as_ref()) {
+            if let Param::Pos(i) = *param {
+                protected.insert(i as usize);
+            }
+        }
+    }
+    let editable = |instr: &Instr| (instr.addr..instr.next()).all(|i| !protected.contains(&i));
+
+    let mut out = mem.to_vec();
+    let mut noops = HashSet::new();
+    for instr in code.values().filter(|instr| editable(instr)) {
+        if noop_target(instr) || dead_store(instr, &code) {
+            noops.insert(instr.addr);
+        } else if let Some(words) = fold(instr) {
+            out[instr.addr..instr.next()].copy_from_slice(&words);
+        }
+    }
+
+    // each no-op jumps straight past the whole run it's part of, unless it's the last one, where the
+    // jump would cost as much as the no-op
+    for &addr in &noops {
+        let mut end = code[&addr].next();
+        while noops.contains(&end) {
+            end = code[&end].next();
+        }
+        if end != code[&addr].next() {
+            out[addr..addr + 3].copy_from_slice(&[1105, 1, end as i64]);
+        }
+    }
+    Ok(out)
+}
+
+/// Runs both programs on the same inputs and compares their outputs and how they stopped
+pub fn same_behaviour(a: &[i64], b: &[i64], inputs: &[i64], limits: Limits) -> bool {
+    let run = |mem: &[i64]| {
+        let mut com = Computer::init(mem, inputs.iter().copied());
+        let stop = run(&mut com, limits);
+        (com.recv_all().collect::<Vec<_>>(), stop)
+    };
+    run(a) == run(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{optimize, run, same_behaviour, OptimizeError};
+    use crate::intcode::Computer;
+    use crate::intcode::fuzz::Limits;
+
+    const LIMITS: Limits = Limits { steps: 10_000_000, mem: 1 << 20 };
+
+    /// Optimises `src` for `patches` and `inputs`, then checks that both versions give the same output,
+    /// stop the same way and leave every word the optimizer didn't touch the same
+    fn check(src: &str, patches: &[(usize, i64)], inputs: &[i64]) {
+        let mut mem = Computer::load(src).unwrap();
+        for &(index, val) in patches {
+            mem[index] = val;
+        }
+        let patched = patches.iter().map(|&(index, _)| index).collect();
+        let opt = optimize(&mem, &patched).unwrap();
+        assert!(same_behaviour(&mem, &opt, inputs, LIMITS));
+
+        let finish = |image: &[i64]| {
+            let mut com = Computer::init(image, inputs.iter().copied());
+            let stop = run(&mut com, LIMITS);
+            (stop, com.recv_all().collect::<Vec<_>>(), com.mem)
+        };
+        let (a, b) = (finish(&mem), finish(&opt));
+        assert_eq!((a.0, &a.1), (b.0, &b.1));
+        assert_eq!(a.2.len(), b.2.len());
+        for (i, (x, y)) in a.2.iter().zip(&b.2).enumerate() {
+            match mem.get(i) == opt.get(i) {
+                true => assert_eq!(x, y, "address {}", i),
+                false => assert_eq!((Some(x), Some(y)), (mem.get(i), opt.get(i)), "rewritten address {}", i),
+            }
+        }
+    }
+
+    #[test]
+    fn day2() {
+        check(include_str!("../../input/2019/day2.txt"), &[(1, 12), (2, 2)], &[]);
+    }
+
+    #[test]
+    fn day5_builds_code_from_its_input() {
+        let mem = Computer::load(include_str!("../../input/2019/day5.txt")).unwrap();
+        assert_eq!(optimize(&mem, &Default::default()), Err(OptimizeError::UnknownCode { addr: 6 }));
+    }
+
+    #[test]
+    fn day9_and_day13_write_through_the_relative_base() {
+        for &src in &[include_str!("../../input/2019/day9.txt"), include_str!("../../input/2019/day13.txt")] {
+            let mem = Computer::load(src).unwrap();
+            match optimize(&mem, &Default::default()) {
+                Err(OptimizeError::RelativeWrite { .. }) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_writes_and_jumps_are_turned_down() {
+        let reject = |mem: &[i64]| optimize(mem, &Default::default()).unwrap_err();
+        assert_eq!(reject(&[109, 10, 21101, 1, 2, 0, 99]), OptimizeError::RelativeWrite { ptr: 2 });
+        assert_eq!(reject(&[5, 3, 3, 99]), OptimizeError::ComputedJump { ptr: 0 });
+        assert_eq!(reject(&[1101, 1, 2, 9, 5, 9]), OptimizeError::UnknownCode { addr: 4 });
+        // a loop that counts down by rewriting its own immediate is fine, one that rewrites an address isn't
+        let mem = vec![1101, 3, -1, 1, 1005, 1, 0, 99];
+        assert!(optimize(&mem, &Default::default()).is_ok());
+        let mem = vec![101, -1, 13, 13, 1001, 5, 1, 5, 1005, 13, 0, 99, 0, 5];
+        assert_eq!(reject(&mem), OptimizeError::SelfModifying { ptr: 4, target: 5 });
+    }
+
+    #[test]
+    fn rewritten_code_that_never_runs_again_is_fine() {
+        // the first instruction's target is rewritten once it's done, like day 2 does
+        let mem = vec![1101, 2, 3, 3, 1101, 4, 5, 12, 4, 12, 99, 0, 0];
+        let opt = optimize(&mem, &Default::default()).unwrap();
+        assert!(same_behaviour(&mem, &opt, &[], LIMITS));
+    }
+
+    #[test]
+    fn jumps_to_themselves_move_on() {
+        let mem = vec![1105, 1, 0, 104, 5, 99];
+        let opt = optimize(&mem, &Default::default()).unwrap();
+        assert!(same_behaviour(&mem, &opt, &[], LIMITS));
+    }
+
+    #[test]
+    fn overflowing_constants_are_left_alone() {
+        let mem = vec![1101, i64::MAX, 1, 7, 4, 7, 99, 0];
+        assert_eq!(optimize(&mem, &Default::default()).unwrap(), mem);
+        let mem = vec![1102, i64::MAX, 2, 7, 4, 7, 99, 0];
+        assert_eq!(optimize(&mem, &Default::default()).unwrap(), mem);
+    }
+
+    #[test]
+    fn stores_before_an_input_stay() {
+        // mem[9] = 5, then input overwrites it; the host sees 5 while the program waits, so the store is
+        // folded but not removed
+        let mem = vec![1101, 2, 3, 9, 3, 9, 4, 9, 99, 0];
+        let opt = optimize(&mem, &Default::default()).unwrap();
+        assert_eq!(opt, vec![1101, 5, 0, 9, 3, 9, 4, 9, 99, 0]);
+    }
+
+    #[test]
+    fn single_noops_are_left_alone() {
+        let mem = vec![1105, 1, 3, 99];
+        assert_eq!(optimize(&mem, &Default::default()).unwrap(), mem);
+        // the first of a run skips the lot, the last would only skip itself
+        let mem = vec![1105, 1, 3, 1105, 1, 6, 1106, 1, 0, 99];
+        assert_eq!(optimize(&mem, &Default::default()).unwrap(), vec![1105, 1, 9, 1105, 1, 9, 1106, 1, 0, 99]);
+    }
+}
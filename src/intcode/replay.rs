@@ -0,0 +1,217 @@
+use crate::intcode::Computer;
+use crate::intcode::fuzz::{Crash, Limits};
+use std::fmt::{Display, Formatter, Error};
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+/// An I/O event and the number of instructions the computer had run before it
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Event {
+    Input { step: u64, val: i64 },
+    Output { step: u64, val: i64 },
+    Halt { step: u64 },
+}
+
+impl Event {
+    /// Same kind and value. Steps only say when it happened, which a change to the program can move
+    /// without changing what it does.
+    pub fn same(&self, other: &Event) -> bool {
+        match (self, other) {
+            (Event::Input { val: a, .. }, Event::Input { val: b, .. }) => a == b,
+            (Event::Output { val: a, .. }, Event::Output { val: b, .. }) => a == b,
+            (Event::Halt { .. }, Event::Halt { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Event::Input { step, val } => write!(f, "in {} {}", step, val),
+            Event::Output { step, val } => write!(f, "out {} {}", step, val),
+            Event::Halt { step } => write!(f, "halt {}", step),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Session {
+    pub events: Vec<Event>,
+}
+
+/// Where a replay first stopped matching its log. `None` means that side had already run out, and
+/// `stopped` says why the replay ended early if it did.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<Event>,
+    pub found: Option<Event>,
+    pub stopped: Option<Crash>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let show = |event: Option<Event>| event.map_or("nothing".to_string(), |e| format!("`{}`", e));
+        write!(f, "event {}: expected {}, found {}", self.index, show(self.expected), show(self.found))?;
+        match &self.stopped {
+            Some(crash) => write!(f, " ({})", crash),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Session {
+    pub(super) fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item=i64> + '_ {
+        self.events.iter().filter_map(|event| match *event {
+            Event::Input { val, .. } => Some(val),
+            _ => None,
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item=i64> + '_ {
+        self.events.iter().filter_map(|event| match *event {
+            Event::Output { val, .. } => Some(val),
+            _ => None,
+        })
+    }
+
+    pub fn parse(log: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (n, line) in log.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let num = |i: usize| words.get(i)
+                .and_then(|word| word.parse::<i64>().ok())
+                .ok_or_else(|| format!("line {}: bad event `{}`", n + 1, line));
+            events.push(match (words[0], words.len()) {
+                ("in", 3) => Event::Input { step: num(1)? as u64, val: num(2)? },
+                ("out", 3) => Event::Output { step: num(1)? as u64, val: num(2)? },
+                ("halt", 2) => Event::Halt { step: num(1)? as u64 },
+                _ => return Err(format!("line {}: bad event `{}`", n + 1, line)),
+            });
+        }
+        Ok(Session { events })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Session::parse(&fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Runs `mem` on the logged inputs and compares what it does with the log. The run stops early on
+    /// anything the VM can't run safely, or after `limits.steps` instructions.
+    pub fn replay(&self, mem: &[i64], limits: Limits) -> Result<(), Divergence> {
+        let mut com = Computer::init(mem, self.inputs());
+        com.record();
+        let mut from = 0;
+        let stopped = loop {
+            if com.steps >= limits.steps {
+                break Some(Crash::StepLimit { steps: com.steps });
+            }
+            if let Err(crash) = com.check_step(from, limits.mem) {
+                break Some(crash);
+            }
+            let ptr = com.ptr;
+            from = ptr;
+            match panic::catch_unwind(AssertUnwindSafe(|| com.step())) {
+                Ok(true) => {}
+                Ok(false) => break None,
+                Err(err) => {
+                    let msg = err.downcast_ref::<&str>().map(|s| s.to_string())
+                        .or_else(|| err.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    break Some(Crash::Panic { ptr, msg });
+                }
+            }
+        };
+        let found = com.take_session().unwrap_or_default();
+        self.diverges_from(&found).map_err(|divergence| Divergence { stopped, ..divergence })
+    }
+
+    /// The first event that differs in kind or value
+    pub fn diverges_from(&self, other: &Session) -> Result<(), Divergence> {
+        let len = self.events.len().max(other.events.len());
+        let same = |i: usize| match (self.events.get(i), other.events.get(i)) {
+            (Some(a), Some(b)) => a.same(b),
+            _ => false,
+        };
+        match (0..len).find(|&i| !same(i)) {
+            Some(index) => Err(Divergence {
+                index,
+                expected: self.events.get(index).copied(),
+                found: other.events.get(index).copied(),
+                stopped: None,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        writeln!(f, "# intcode session, {} events", self.events.len())?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl Computer {
+    /// Starts logging every input consumed and output produced from here on
+    pub fn record(&mut self) {
+        self.session = Some(Session::default());
+    }
+
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    pub fn take_session(&mut self) -> Option<Session> {
+        self.session.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Session};
+    use crate::intcode::fuzz::{Crash, Limits};
+
+    // outputs each input plus one until it reads 0
+    const INC: [i64; 16] = [3, 15, 1006, 15, 14, 1001, 15, 1, 15, 4, 15, 1105, 1, 0, 99, 0];
+
+    #[test]
+    fn steps_dont_count() {
+        let log = Session::parse("in 0 5\nout 3 6\nin 5 0\nhalt 7").unwrap();
+        assert_eq!(log.replay(&INC, Limits::default()), Ok(()));
+        let moved = Session::parse("in 100 5\nout 200 6\nin 300 0\nhalt 400").unwrap();
+        assert_eq!(moved.replay(&INC, Limits::default()), Ok(()));
+        let wrong = Session::parse("in 0 5\nout 3 7\nin 5 0\nhalt 7").unwrap();
+        let divergence = wrong.replay(&INC, Limits::default()).unwrap_err();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(divergence.found, Some(Event::Output { step: 3, val: 6 }));
+    }
+
+    #[test]
+    fn runaway_replays_stop() {
+        let log = Session::parse("out 0 1\nhalt 1").unwrap();
+        let divergence = log.replay(&[1105, 1, 3, 1105, 1, 0], Limits { steps: 1000, mem: 1 << 10 }).unwrap_err();
+        assert_eq!(divergence.stopped, Some(Crash::StepLimit { steps: 1000 }));
+        let divergence = log.replay(&[42], Limits::default()).unwrap_err();
+        assert_eq!(divergence.stopped, Some(Crash::InvalidOpcode { ptr: 0, instr: 42 }));
+    }
+}
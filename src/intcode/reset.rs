@@ -0,0 +1,192 @@
+use crate::intcode::{Computer, state};
+use crate::intcode::calls::CallStack;
+use crate::intcode::replay::Session;
+use std::sync::Arc;
+
+const PAGE_BITS: usize = 6;
+const PAGE: usize = 1 << PAGE_BITS;
+
+/// The image a `Computer` started from, and which pages of it have been written since
+#[derive(Debug)]
+pub struct Pristine {
+    image: Arc<Vec<i64>>,
+    dirty: Vec<u64>,
+}
+
+impl Pristine {
+    fn new(image: Arc<Vec<i64>>) -> Self {
+        let pages = image.len().div_ceil(PAGE);
+        Pristine { image, dirty: vec![0; pages.div_ceil(64)] }
+    }
+
+    fn all_dirty(&mut self) {
+        for bits in &mut self.dirty {
+            *bits = !0;
+        }
+    }
+}
+
+/// Marks the page holding `index` as needing a rewrite on the next reset. Anything past the end of the
+/// image is cut off by the reset anyway.
+pub(super) fn touch(pristine: &mut Option<Pristine>, index: usize) {
+    if let Some(pristine) = pristine {
+        let page = index >> PAGE_BITS;
+        if let Some(bits) = pristine.dirty.get_mut(page / 64) {
+            *bits |= 1 << (page % 64);
+        }
+    }
+}
+
+/// Same as `touch` for every page, for when all of memory was replaced
+pub(super) fn touch_all(pristine: &mut Option<Pristine>) {
+    if let Some(pristine) = pristine {
+        pristine.all_dirty();
+    }
+}
+
+impl Computer {
+    /// A `Computer` that can be `reset` back to `image`, sharing it with any others made from it
+    pub fn from_image<I: IntoIterator<Item=i64>>(image: Arc<Vec<i64>>, vals: I) -> Self {
+        let mut com = Computer::init(&image, vals);
+        com.pristine = Some(Pristine::new(image));
+        com
+    }
+
+    /// Remembers the current memory as the image to `reset` to
+    pub fn keep_image(&mut self) {
+        self.pristine = Some(Pristine::new(Arc::new(self.mem.clone())));
+    }
+
+    /// Puts memory back to the kept image and starts over with `vals` as input, reusing the allocation.
+    /// Only pages the program wrote to get copied back, so writes made to `mem` directly instead of
+    /// through `patch` are missed. History, the session, the call stack, strict mode and taint start
+    /// over too, staying on but dropping what they'd recorded, so take anything worth keeping first.
+    /// Usage counts carry on and add up over every run.
+    pub fn reset<I: IntoIterator<Item=i64>>(&mut self, vals: I) {
+        let Pristine { image, dirty } = self.pristine.as_mut()
+            .expect("reset needs a kept image, see from_image or keep_image");
+        if self.mem.len() < image.len() {
+            self.mem.clear();
+            self.mem.extend_from_slice(image);
+            dirty.iter_mut().for_each(|bits| *bits = 0);
+        } else {
+            self.mem.truncate(image.len());
+            for (word, bits) in dirty.iter_mut().enumerate() {
+                while *bits != 0 {
+                    let page = word * 64 + bits.trailing_zeros() as usize;
+                    *bits &= *bits - 1;
+                    let start = page << PAGE_BITS;
+                    let end = (start + PAGE).min(image.len());
+                    if start < end {
+                        self.mem[start..end].copy_from_slice(&image[start..end]);
+                    }
+                }
+            }
+        }
+        self.ptr = 0;
+        self.rel_base = 0;
+        self.input = vals.into_iter().collect();
+        self.output.clear();
+        self.is_done = false;
+        self.steps = 0;
+        self.refresh_fingerprint();
+        let len = self.mem.len();
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        if let Some(session) = &mut self.session {
+            *session = Session::default();
+        }
+        if let Some(calls) = &mut self.calls {
+            *calls = CallStack::default();
+        }
+        if let Some(strict) = &mut self.strict {
+            strict.clear(len);
+        }
+        if let Some(taint) = &mut self.taint {
+            taint.clear(len);
+        }
+    }
+
+    /// Sets `mem[index]` so that the next `reset` puts it back, keeping the fingerprint and analyses
+    /// up to date the way a write by the program would. The cell counts as initialised and untainted,
+    /// and with history on the patch is undone along with the last instruction.
+    pub fn patch(&mut self, index: usize, val: i64) {
+        if index >= self.mem.len() {
+            self.mem.resize(index + 1, 0);
+        }
+        if let Some(history) = &mut self.history {
+            history.write(index, self.mem[index], val);
+        }
+        if let Some(strict) = &mut self.strict {
+            strict.written(index);
+        }
+        if let Some(taint) = &mut self.taint {
+            taint.untaint(index);
+        }
+        if let Some(usage) = &mut self.usage {
+            usage.written(index);
+        }
+        state::rewrite(&mut self.fingerprint, index, self.mem[index], val);
+        touch(&mut self.pristine, index);
+        self.mem[index] = val;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::Computer;
+    use crate::intcode::strict::Strictness;
+    use crate::intcode::taint::Flow;
+    use std::sync::Arc;
+
+    // in [10]; out [10]; out [11]; halt
+    const PROG: [i64; 7] = [3, 10, 4, 10, 4, 11, 99];
+
+    #[test]
+    fn patches_are_bookkept_like_writes() {
+        let mut com = Computer::from_image(Arc::new(PROG.to_vec()), vec![5]);
+        com.track_fingerprint();
+        com.strict(Strictness::Deny);
+        com.track_usage();
+        com.patch(11, 7);
+        let fingerprint = com.fingerprint();
+        com.refresh_fingerprint();
+        assert_eq!(com.fingerprint(), fingerprint);
+        com.compute();
+        assert!(com.is_done);
+        assert!(com.uninit_reads().is_empty());
+        assert_eq!(com.output.iter().copied().collect::<Vec<_>>(), vec![5, 7]);
+        assert_eq!(com.usage().map(|usage| usage.len()), Some(12));
+    }
+
+    #[test]
+    fn patches_are_undone_with_the_last_instruction() {
+        let mut com = Computer::init(&PROG, vec![5]);
+        com.record_history(10, 4);
+        com.step();
+        com.patch(10, 6);
+        assert!(com.step_back());
+        assert_eq!((com.ptr, com.mem[10]), (0, 0));
+    }
+
+    #[test]
+    fn reset_starts_the_analyses_over() {
+        let mut com = Computer::from_image(Arc::new(PROG.to_vec()), vec![5]);
+        com.strict(Strictness::Warn);
+        com.track_taint(Flow::Data);
+        com.record_history(10, 4);
+        com.track_calls();
+        com.compute();
+        assert_eq!(com.uninit_reads().len(), 1);
+        assert_eq!(com.tainted_outputs().len(), 2);
+        com.reset(vec![6]);
+        assert!(com.uninit_reads().is_empty());
+        assert!(com.tainted_outputs().is_empty());
+        assert_eq!(com.history().map(|history| history.len()), Some(0));
+        assert!(!com.step_back());
+        com.compute();
+        assert_eq!(com.uninit_reads().len(), 1);
+        assert_eq!(com.taint_of(10), vec![0]);
+    }
+}
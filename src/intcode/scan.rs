@@ -0,0 +1,256 @@
+use crate::intcode::Computer;
+use std::fmt::{Display, Formatter, Error};
+
+/// Memory at one point of a run, along with the latest output at that point
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    pub mem: Vec<i64>,
+    pub steps: u64,
+    pub last_output: Option<i64>,
+}
+
+impl Snapshot {
+    /// Memory past the end reads as 0, same as in the VM
+    pub fn get(&self, addr: usize) -> i64 {
+        self.mem.get(addr).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Change {
+    pub addr: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "[{}] {} -> {}", self.addr, self.old, self.new)
+    }
+}
+
+pub fn diff(a: &Snapshot, b: &Snapshot) -> Vec<Change> {
+    (0..a.mem.len().max(b.mem.len()))
+        .map(|addr| Change { addr, old: a.get(addr), new: b.get(addr) })
+        .filter(|change| change.old != change.new)
+        .collect()
+}
+
+/// What a candidate address has to have done between the last two snapshots
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Predicate {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    ChangedBy(i64),
+    Equals(i64),
+    LastOutput,
+}
+
+impl Predicate {
+    fn holds(self, old: i64, new: i64, last_output: Option<i64>) -> bool {
+        match self {
+            Predicate::Changed => old != new,
+            Predicate::Unchanged => old == new,
+            Predicate::Increased => new > old,
+            Predicate::Decreased => new < old,
+            Predicate::ChangedBy(d) => new.checked_sub(old) == Some(d),
+            Predicate::Equals(v) => new == v,
+            Predicate::LastOutput => Some(new) == last_output,
+        }
+    }
+}
+
+/// Narrows down which addresses hold some piece of program state by filtering on how they change
+/// from one snapshot to the next, cheat engine style
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    candidates: Vec<usize>,
+    snapshots: Vec<Snapshot>,
+    // each filter so far with the snapshot it was applied to, for addresses that show up later
+    filters: Vec<(usize, Predicate)>,
+}
+
+impl Scanner {
+    /// Starts with every address as a candidate and `com`'s current memory as the first snapshot
+    pub fn new(com: &Computer) -> Self {
+        let first = com.snapshot(None);
+        Scanner {
+            candidates: (0..first.mem.len()).collect(),
+            snapshots: vec![first],
+            filters: Vec::new(),
+        }
+    }
+
+    /// Takes a snapshot of `com` as it is now. Addresses the program has grown memory to since the
+    /// last one read as 0 until now, and they become candidates if that passes every filter so far.
+    pub fn snapshot(&mut self, com: &Computer) -> &Snapshot {
+        let last_output = com.output.back().copied()
+            .or_else(|| self.snapshots.last().and_then(|snap| snap.last_output));
+        let seen = self.snapshots.iter().map(|snap| snap.mem.len()).max().unwrap_or(0);
+        self.snapshots.push(com.snapshot(last_output));
+        let grown: Vec<usize> = (seen..com.mem.len())
+            .filter(|&addr| self.filters.iter().all(|&(i, pred)| self.holds(pred, i, addr)))
+            .collect();
+        self.candidates.extend(grown);
+        self.snapshots.last().unwrap()
+    }
+
+    /// Whether `pred` holds for `addr` between snapshot `i` and the one before it, or `i` itself if
+    /// it's the first
+    fn holds(&self, pred: Predicate, i: usize, addr: usize) -> bool {
+        let (old, new) = (&self.snapshots[i.saturating_sub(1)], &self.snapshots[i]);
+        pred.holds(old.get(addr), new.get(addr), new.last_output)
+    }
+
+    /// Sends `input`, runs `com` until it wants more input or halts, then takes a snapshot
+    pub fn advance<I: IntoIterator<Item=i64>>(&mut self, com: &mut Computer, input: I) -> &Snapshot {
+        com.send_all(input.into_iter());
+        com.compute();
+        self.snapshot(com)
+    }
+
+    /// Drops the candidates that `pred` doesn't hold for between the last two snapshots. With only
+    /// one snapshot, every address counts as unchanged.
+    pub fn filter(&mut self, pred: Predicate) -> &[usize] {
+        let last = self.snapshots.len() - 1;
+        let mut candidates = std::mem::take(&mut self.candidates);
+        candidates.retain(|&addr| self.holds(pred, last, addr));
+        self.candidates = candidates;
+        self.filters.push((last, pred));
+        &self.candidates
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    /// Changes between the last two snapshots
+    pub fn last_diff(&self) -> Vec<Change> {
+        match &self.snapshots[..] {
+            [.., old, new] => diff(old, new),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The value of `addr` in every snapshot
+    pub fn history(&self, addr: usize) -> Vec<i64> {
+        self.snapshots.iter().map(|snap| snap.get(addr)).collect()
+    }
+
+    /// Each remaining candidate with its history
+    pub fn results(&self) -> Vec<(usize, Vec<i64>)> {
+        self.candidates.iter().map(|&addr| (addr, self.history(addr))).collect()
+    }
+}
+
+impl Computer {
+    fn snapshot(&self, last_output: Option<i64>) -> Snapshot {
+        Snapshot {
+            mem: self.mem.clone(),
+            steps: self.steps,
+            last_output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Change, Predicate, Scanner, Snapshot};
+    use crate::intcode::Computer;
+    use std::iter::empty;
+
+    // each round adds the input to a total at 20, outputs it and counts down a number at 21
+    const TOTAL: [i64; 23] = [
+        3, 22, 1, 20, 22, 20, 1001, 21, -1, 21, 4, 20, 1105, 1, 0,
+        0, 0, 0, 0, 0, 0, 100, 0,
+    ];
+
+    #[test]
+    fn filters_narrow_down_candidates() {
+        let mut com = Computer::init(&TOTAL, empty());
+        let mut scan = Scanner::new(&com);
+        scan.advance(&mut com, vec![5]);
+        // the total and the input cell both went up
+        assert_eq!(scan.filter(Predicate::Increased), &[20, 22]);
+        scan.advance(&mut com, vec![3]);
+        assert_eq!(scan.filter(Predicate::LastOutput), &[20]);
+        assert_eq!(scan.results(), vec![(20, vec![0, 5, 8])]);
+
+        let mut com = Computer::init(&TOTAL, empty());
+        let mut scan = Scanner::new(&com);
+        scan.advance(&mut com, vec![5]);
+        assert_eq!(scan.filter(Predicate::Changed), &[20, 21, 22]);
+        assert_eq!(scan.filter(Predicate::Decreased), &[21]);
+        scan.advance(&mut com, vec![5]);
+        assert_eq!(scan.filter(Predicate::ChangedBy(-1)), &[21]);
+        assert_eq!(scan.filter(Predicate::Equals(98)), &[21]);
+        scan.advance(&mut com, vec![0]);
+        assert_eq!(scan.filter(Predicate::Unchanged), &[] as &[usize]);
+    }
+
+    #[test]
+    fn with_one_snapshot_nothing_changed() {
+        let com = Computer::init(&TOTAL, empty());
+        let mut scan = Scanner::new(&com);
+        assert_eq!(scan.filter(Predicate::Changed), &[] as &[usize]);
+        let mut scan = Scanner::new(&com);
+        assert_eq!(scan.filter(Predicate::Unchanged).len(), TOTAL.len());
+    }
+
+    #[test]
+    fn changed_by_doesnt_overflow() {
+        assert!(!Predicate::ChangedBy(-1).holds(i64::MIN, i64::MAX, None));
+        assert!(!Predicate::ChangedBy(1).holds(i64::MAX, i64::MIN, None));
+        assert!(Predicate::ChangedBy(i64::MAX).holds(-1, i64::MAX - 1, None));
+    }
+
+    #[test]
+    fn grown_memory_joins_the_candidates() {
+        // stores each input far past the end of the program
+        let prog = [3, 1000, 1105, 1, 0];
+        let mut com = Computer::init(&prog, empty());
+        let mut scan = Scanner::new(&com);
+        assert_eq!(scan.filter(Predicate::Equals(0)), &[4]);
+        scan.advance(&mut com, vec![7]);
+        assert_eq!(scan.filter(Predicate::Equals(7)), &[1000]);
+        assert_eq!(scan.history(1000), vec![0, 7]);
+
+        // 1000 read as 0 at the start, so it never passed the first filter
+        let mut com = Computer::init(&prog, empty());
+        let mut scan = Scanner::new(&com);
+        scan.filter(Predicate::Equals(42));
+        scan.advance(&mut com, vec![7]);
+        assert_eq!(scan.candidates(), &[] as &[usize]);
+    }
+
+    #[test]
+    fn diffs_cover_both_lengths() {
+        let snap = |mem: Vec<i64>| Snapshot { mem, steps: 0, last_output: None };
+        let (a, b) = (snap(vec![1, 2, 3]), snap(vec![1, 5]));
+        assert_eq!(diff(&a, &b), vec![Change { addr: 1, old: 2, new: 5 }, Change { addr: 2, old: 3, new: 0 }]);
+        assert_eq!(diff(&b, &a), vec![Change { addr: 1, old: 5, new: 2 }, Change { addr: 2, old: 0, new: 3 }]);
+        assert_eq!(diff(&a, &a), vec![]);
+    }
+
+    #[test]
+    fn last_diff_is_between_the_latest_snapshots() {
+        let mut com = Computer::init(&TOTAL, empty());
+        let mut scan = Scanner::new(&com);
+        assert_eq!(scan.last_diff(), vec![]);
+        scan.advance(&mut com, vec![5]);
+        scan.advance(&mut com, vec![2]);
+        assert_eq!(scan.last_diff(), vec![
+            Change { addr: 20, old: 5, new: 7 },
+            Change { addr: 21, old: 99, new: 98 },
+            Change { addr: 22, old: 5, new: 2 },
+        ]);
+        assert_eq!(scan.snapshots().len(), 3);
+        assert_eq!(scan.snapshots()[2].last_output, Some(7));
+    }
+}
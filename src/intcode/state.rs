@@ -0,0 +1,178 @@
+use crate::intcode::Computer;
+use std::hash::{Hash, Hasher};
+
+fn mix(mut n: u64) -> u64 {
+    n = (n ^ (n >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    n = (n ^ (n >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    n ^ (n >> 31)
+}
+
+/// What one cell adds to the memory fingerprint, zero cells add nothing so trailing zeros don't count
+fn cell(index: usize, val: i64) -> u64 {
+    match val {
+        0 => 0,
+        val => mix(mix(index as u64) ^ val as u64),
+    }
+}
+
+fn mem_fingerprint(mem: &[i64]) -> u64 {
+    mem.iter().enumerate().fold(0, |fp, (i, &val)| fp ^ cell(i, val))
+}
+
+/// Keeps a tracked fingerprint up to date with `mem[index]` going from `old` to `new`
+pub(super) fn rewrite(fingerprint: &mut Option<u64>, index: usize, old: i64, new: i64) {
+    if let Some(fp) = fingerprint {
+        *fp ^= cell(index, old) ^ cell(index, new);
+    }
+}
+
+impl Computer {
+    /// Keeps the memory part of the fingerprint up to date on every write from now on, instead of
+    /// hashing all of memory each time it's asked for
+    pub fn track_fingerprint(&mut self) {
+        self.fingerprint = Some(mem_fingerprint(&self.mem));
+    }
+
+    /// Needed after changing `mem` directly while the fingerprint is tracked
+    pub fn refresh_fingerprint(&mut self) {
+        if self.fingerprint.is_some() {
+            self.track_fingerprint();
+        }
+    }
+
+    /// Covers the state `Eq` compares: memory without trailing zeros, the pointer, relative base,
+    /// both queues and whether it's halted
+    pub fn fingerprint(&self) -> u64 {
+        let mut fp = self.fingerprint.unwrap_or_else(|| mem_fingerprint(&self.mem));
+        let regs = [self.ptr as u64, self.rel_base as u64, self.is_done as u64, self.input.len() as u64, self.output.len() as u64];
+        for n in regs.iter().copied().chain(self.input.iter().chain(&self.output).map(|&n| n as u64)) {
+            fp = mix(fp ^ n);
+        }
+        fp
+    }
+}
+
+/// Two computers are equal when they'd behave the same from here on: same memory (with memory past
+/// the end reading as 0, trailing zeros don't matter), pointer, relative base, pending input and
+/// output, and halted flag. Step counts, history, hooks and analyses aren't part of the state.
+impl PartialEq for Computer {
+    fn eq(&self, other: &Self) -> bool {
+        // compares memory itself, a tracked fingerprint goes stale if `mem` is changed directly
+        let common = self.mem.len().min(other.mem.len());
+        self.ptr == other.ptr
+            && self.rel_base == other.rel_base
+            && self.is_done == other.is_done
+            && self.input == other.input
+            && self.output == other.output
+            && self.mem[..common] == other.mem[..common]
+            && self.mem[common..].iter().chain(&other.mem[common..]).all(|&n| n == 0)
+    }
+}
+
+impl Eq for Computer {}
+
+/// Hashes the fingerprint, so with tracking on, `refresh_fingerprint` has to follow any direct change
+/// to `mem` before the computer goes in a set
+impl Hash for Computer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.fingerprint());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::Computer;
+    use std::collections::HashSet;
+    use std::iter::empty;
+
+    // adds its two inputs into 11 and outputs the sum
+    const SUM: [i64; 13] = [3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0];
+
+    #[test]
+    fn trailing_zeros_dont_count() {
+        let a = Computer::init(&SUM, empty());
+        let mut padded = SUM.to_vec();
+        padded.extend(vec![0; 100]);
+        let mut b = Computer::init(&padded, empty());
+        assert_eq!(a, b);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        b.track_fingerprint();
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        b.mem[50] = 1;
+        b.refresh_fingerprint();
+        assert_ne!(a, b);
+        assert_ne!(a.fingerprint(), b.fingerprint());
+    }
+
+    #[test]
+    fn registers_and_queues_count() {
+        let fresh = || Computer::init(&SUM, empty());
+        let base = fresh();
+        let mut ptr = fresh();
+        ptr.ptr = 2;
+        let mut rel_base = fresh();
+        rel_base.rel_base = 5;
+        let mut done = fresh();
+        done.is_done = true;
+        let mut input = fresh();
+        input.send(1);
+        let mut output = fresh();
+        output.output.push_back(1);
+        for other in &[ptr, rel_base, done, input, output] {
+            assert_ne!(&base, other);
+            assert_ne!(base.fingerprint(), other.fingerprint());
+        }
+    }
+
+    #[test]
+    fn steps_dont_count() {
+        let mut a = Computer::init(&SUM, vec![2]);
+        a.step();
+        let mut b = Computer::init(&SUM, empty());
+        b.mem[11] = 2;
+        b.ptr = 2;
+        assert_eq!(a, b);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+    }
+
+    #[test]
+    fn tracking_follows_every_write() {
+        let mut tracked = Computer::init(&SUM, vec![2, 3]);
+        tracked.track_fingerprint();
+        tracked.compute();
+        let mut fresh = Computer::init(&SUM, vec![2, 3]);
+        fresh.compute();
+        assert_eq!(tracked, fresh);
+        assert_eq!(tracked.fingerprint(), fresh.fingerprint());
+    }
+
+    #[test]
+    fn direct_changes_to_memory_count_even_when_tracked() {
+        // a's tracked fingerprint still has 0 at 11, b's has 7, but their memory is the same
+        let mut a = Computer::init(&SUM, empty());
+        a.track_fingerprint();
+        a.mem[11] = 7;
+        let mut b = Computer::init(&SUM, empty());
+        b.mem[11] = 7;
+        b.track_fingerprint();
+        assert_eq!(a, b);
+        b.mem[11] = 8;
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn sets_keep_one_of_each_state() {
+        let mut seen = HashSet::new();
+        for &(x, y) in &[(1, 4), (2, 3), (3, 2), (5, 5)] {
+            let mut com = Computer::init(&SUM, vec![x, y]);
+            com.track_fingerprint();
+            com.compute();
+            com.output.clear();
+            // the second input is left in 12, drop it so only the sum matters
+            com.mem[12] = 0;
+            com.refresh_fingerprint();
+            seen.insert(com);
+        }
+        assert_eq!(seen.len(), 2);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::intcode::{Computer, Opcode, Mode};
+use crate::intcode::calls::CallStack;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Error};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Strictness {
+    /// record the read and carry on with it as 0
+    Warn,
+    /// record the read and stop before the instruction runs
+    Deny,
+}
+
+/// A read of a cell that was neither in the image nor written since
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UninitRead {
+    pub ptr: usize,
+    pub index: usize,
+    pub step: u64,
+    /// the calls that led to the read, empty unless calls are tracked
+    pub stack: CallStack,
+}
+
+impl Display for UninitRead {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "step {}: instruction at {} reads uninitialised memory at {}", self.step, self.ptr, self.index)?;
+        if self.stack.depth() > 0 {
+            write!(f, "\n{}", self.stack)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct Strict {
+    level: Strictness,
+    init: Vec<bool>,
+    reads: Vec<UninitRead>,
+    // (ptr, index) pairs already in `reads`, so a denied instruction that's retried isn't counted again
+    seen: HashSet<(usize, usize)>,
+    fault: Option<UninitRead>,
+}
+
+impl Strict {
+    /// Forgets the flagged reads and counts the first `len` cells as initialised, for when the program
+    /// starts over
+    pub(super) fn clear(&mut self, len: usize) {
+        self.init.clear();
+        self.init.resize(len, true);
+        self.reads.clear();
+        self.seen.clear();
+        self.fault = None;
+    }
+
+    pub(super) fn written(&mut self, index: usize) {
+        if index >= self.init.len() {
+            self.init.resize(index + 1, false);
+        }
+        self.init[index] = true;
+    }
+}
+
+impl Computer {
+    /// Starts tracking which cells have been initialised, everything currently in `mem` counts
+    pub fn strict(&mut self, level: Strictness) {
+        self.strict = Some(Strict {
+            level,
+            init: vec![true; self.mem.len()],
+            reads: Vec::new(),
+            seen: HashSet::new(),
+            fault: None,
+        });
+    }
+
+    /// Back to treating all memory as zeroed, giving back the reads that were flagged
+    pub fn permissive(&mut self) -> Vec<UninitRead> {
+        self.strict.take().map_or_else(Vec::new, |strict| strict.reads)
+    }
+
+    pub fn uninit_reads(&self) -> &[UninitRead] {
+        self.strict.as_ref().map_or(&[], |strict| &strict.reads)
+    }
+
+    /// The read that's stopping the program in `Deny` mode
+    pub fn fault(&self) -> Option<&UninitRead> {
+        self.strict.as_ref()?.fault.as_ref()
+    }
+
+    /// Checks the reads `opcode` is about to make, false if it mustn't run
+    pub(super) fn check_reads(&mut self, opcode: &Opcode) -> bool {
+        // whether the jump target gets read depends on the condition, which has to be read the way the
+        // instruction will read it, so a region's on_read sees it twice
+        let mut cond = |mode: &Mode| {
+            let index = mode.index();
+            let val = self.mem.get(index).copied().unwrap_or(0);
+            if self.regions.is_empty() { val } else { self.read_mapped(index, val) }
+        };
+        let reads: Vec<&Mode> = match opcode {
+            Opcode::Add(a, b, _) | Opcode::Mult(a, b, _) | Opcode::Less(a, b, _) | Opcode::Equal(a, b, _) => vec![a, b],
+            Opcode::Output(a) | Opcode::SetRelBase(a) => vec![a],
+            Opcode::JumpNZero(c, j) if cond(c) != 0 => vec![c, j],
+            Opcode::JumpZero(c, j) if cond(c) == 0 => vec![c, j],
+            Opcode::JumpNZero(c, _) | Opcode::JumpZero(c, _) => vec![c],
+            Opcode::Input(_) | Opcode::Halt | Opcode::Ext(_) => vec![],
+        };
+        let Computer { ptr, steps, regions, strict, calls, .. } = self;
+        let strict = strict.as_mut().unwrap();
+        strict.fault = None;
+        for index in reads.into_iter().map(Mode::index) {
+            if strict.init.get(index).copied().unwrap_or(false) || regions.iter().any(|region| region.range().contains(&index)) {
+                continue;
+            }
+            let read = UninitRead { ptr: *ptr, index, step: *steps, stack: calls.clone().unwrap_or_default() };
+            if strict.seen.insert((*ptr, index)) {
+                strict.reads.push(read.clone());
+            }
+            if strict.level == Strictness::Deny {
+                strict.fault = Some(read);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Strictness;
+    use crate::intcode::Computer;
+    use crate::intcode::mmio::Region;
+
+    #[test]
+    fn denied_reads_are_recorded_once() {
+        // out [10], halt
+        let mut com = Computer::init(&[4, 10, 99], None);
+        com.strict(Strictness::Deny);
+        assert!(!com.step());
+        assert!(!com.step());
+        assert_eq!(com.uninit_reads().len(), 1);
+        assert_eq!(com.fault().map(|read| (read.ptr, read.index)), Some((0, 10)));
+    }
+
+    #[test]
+    fn conditions_are_read_through_regions() {
+        // jnz [3], [20]; the condition cell is 0 in memory but a device says 1, so the target is read
+        let mut com = Computer::init(&[5, 3, 20, 0], None);
+        com.map_region(Region::new(3..4).on_read(|_, _| 1));
+        com.strict(Strictness::Warn);
+        com.step();
+        assert_eq!(com.uninit_reads().iter().map(|read| read.index).collect::<Vec<_>>(), vec![20]);
+    }
+}
@@ -0,0 +1,475 @@
+use crate::intcode::Computer;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Error};
+use std::iter::empty;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+
+const STEP_LIMIT: usize = 1_000_000;
+const MEM_LIMIT: usize = 1 << 20;
+
+/// A polynomial over the unknown cells, keyed by the exponent of each unknown.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Poly {
+    terms: BTreeMap<Vec<u32>, i64>,
+}
+
+impl Poly {
+    pub fn constant(n: i64) -> Self {
+        let mut terms = BTreeMap::new();
+        if n != 0 {
+            terms.insert(Vec::new(), n);
+        }
+        Poly { terms }
+    }
+
+    pub fn var(i: usize) -> Self {
+        let mut exps = vec![0; i + 1];
+        exps[i] = 1;
+        let mut terms = BTreeMap::new();
+        terms.insert(exps, 1);
+        Poly { terms }
+    }
+
+    pub fn as_const(&self) -> Option<i64> {
+        match self.terms.len() {
+            0 => Some(0),
+            1 => self.terms.get(&Vec::new()).copied(),
+            _ => None,
+        }
+    }
+
+    /// `None` if it overflows along the way
+    pub fn eval(&self, vals: &[i64]) -> Option<i64> {
+        self.terms.iter().try_fold(0_i64, |sum, (exps, &coef)| {
+            let term = exps.iter()
+                .enumerate()
+                .try_fold(coef, |acc, (i, &exp)| acc.checked_mul(vals[i].checked_pow(exp)?))?;
+            sum.checked_add(term)
+        })
+    }
+
+    pub fn degree_in(&self, i: usize) -> u32 {
+        self.terms.keys()
+            .map(|exps| exps.get(i).copied().unwrap_or(0))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Replaces unknown `i` with `val`
+    pub fn substitute(&self, i: usize, val: i64) -> Option<Self> {
+        let mut poly = Poly::default();
+        for (exps, &coef) in &self.terms {
+            let mut exps = exps.clone();
+            let coef = match exps.get_mut(i) {
+                Some(exp) => coef.checked_mul(val.checked_pow(std::mem::replace(exp, 0))?)?,
+                None => coef,
+            };
+            poly.insert(exps, coef)?;
+        }
+        Some(poly)
+    }
+
+    pub fn checked_add(&self, rhs: &Poly) -> Option<Self> {
+        let mut poly = self.clone();
+        for (exps, &coef) in &rhs.terms {
+            poly.insert(exps.clone(), coef)?;
+        }
+        Some(poly)
+    }
+
+    pub fn checked_neg(&self) -> Option<Self> {
+        let terms = self.terms.iter()
+            .map(|(exps, &coef)| Some((exps.clone(), coef.checked_neg()?)))
+            .collect::<Option<_>>()?;
+        Some(Poly { terms })
+    }
+
+    pub fn checked_sub(&self, rhs: &Poly) -> Option<Self> {
+        self.checked_add(&rhs.checked_neg()?)
+    }
+
+    pub fn checked_mul(&self, rhs: &Poly) -> Option<Self> {
+        let mut poly = Poly::default();
+        for (a, &ca) in &self.terms {
+            for (b, &cb) in &rhs.terms {
+                let exps = (0..a.len().max(b.len()))
+                    .map(|i| a.get(i).unwrap_or(&0) + b.get(i).unwrap_or(&0))
+                    .collect();
+                poly.insert(exps, ca.checked_mul(cb)?)?;
+            }
+        }
+        Some(poly)
+    }
+
+    fn insert(&mut self, mut exps: Vec<u32>, coef: i64) -> Option<()> {
+        while exps.last() == Some(&0) {
+            exps.pop();
+        }
+        let sum = self.terms.get(&exps).copied().unwrap_or(0).checked_add(coef)?;
+        if sum == 0 {
+            self.terms.remove(&exps);
+        } else {
+            self.terms.insert(exps, sum);
+        }
+        Some(())
+    }
+}
+
+impl Display for Poly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (n, (exps, coef)) in self.terms.iter().rev().enumerate() {
+            if n != 0 {
+                write!(f, " + ")?;
+            }
+            write!(f, "{}", coef)?;
+            for (i, &exp) in exps.iter().enumerate() {
+                match exp {
+                    0 => {}
+                    1 => write!(f, "*x{}", i)?,
+                    exp => write!(f, "*x{}^{}", i, exp)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a program couldn't be evaluated symbolically, with the address of the instruction at fault
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Fallback {
+    SymbolicOpcode(usize),
+    SymbolicAddress(usize),
+    SymbolicJump(usize),
+    SymbolicComparison(usize),
+    SymbolicRelBase(usize),
+    UnknownOpcode(usize),
+    NeedsInput(usize),
+    Overflow(usize),
+    /// an address or jump target is negative or past the memory limit
+    BadAddress(usize),
+    OverflowSolving,
+    StepLimit,
+}
+
+impl Display for Fallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Fallback::SymbolicOpcode(ptr) => write!(f, "the instruction at {} depends on the unknowns", ptr),
+            Fallback::SymbolicAddress(ptr) => write!(f, "an operand address at {} depends on the unknowns", ptr),
+            Fallback::SymbolicJump(ptr) => write!(f, "the jump at {} depends on the unknowns", ptr),
+            Fallback::SymbolicComparison(ptr) => write!(f, "the comparison at {} depends on the unknowns", ptr),
+            Fallback::SymbolicRelBase(ptr) => write!(f, "the relative base set at {} depends on the unknowns", ptr),
+            Fallback::UnknownOpcode(ptr) => write!(f, "the instruction at {} isn't a standard opcode", ptr),
+            Fallback::NeedsInput(ptr) => write!(f, "the input at {} ran out of values", ptr),
+            Fallback::Overflow(ptr) => write!(f, "the arithmetic at {} overflows", ptr),
+            Fallback::BadAddress(ptr) => write!(f, "the instruction at {} goes outside memory", ptr),
+            Fallback::OverflowSolving => write!(f, "solving the polynomial overflows"),
+            Fallback::StepLimit => write!(f, "the program ran for more than {} steps", STEP_LIMIT),
+        }
+    }
+}
+
+/// A cell is `Err` once its value came from a read whose address depended on the unknowns. That only
+/// matters if the value is used for something the evaluation can't represent.
+pub type Cell = Result<Poly, Fallback>;
+
+#[derive(Debug, Clone)]
+pub struct SymbolicRun {
+    pub mem: Vec<Cell>,
+    pub outputs: Vec<Cell>,
+}
+
+/// `addr` as an index, if it's one memory can grow to
+fn in_bounds(addr: i64) -> Option<usize> {
+    usize::try_from(addr).ok().filter(|&addr| addr < MEM_LIMIT)
+}
+
+fn combine(a: Cell, b: Cell, f: impl FnOnce(Poly, Poly) -> Cell) -> Cell {
+    f(a?, b?)
+}
+
+/// Runs `mem` with the cells in `unknowns` replaced by `x0`, `x1`, ...
+pub fn evaluate(mem: &[i64], unknowns: &[usize], inputs: &[i64]) -> Result<SymbolicRun, Fallback> {
+    let mut mem: Vec<Cell> = mem.iter().map(|&n| Ok(Poly::constant(n))).collect();
+    for (i, &index) in unknowns.iter().enumerate() {
+        if index >= mem.len() {
+            mem.resize(index + 1, Ok(Poly::default()));
+        }
+        mem[index] = Ok(Poly::var(i));
+    }
+    let mut inputs = inputs.iter();
+    let mut outputs = Vec::new();
+    let mut ptr = 0;
+    let mut rel_base = 0_i64;
+
+    for _ in 0..STEP_LIMIT {
+        let cell = |mem: &Vec<Cell>, i: usize| mem.get(i).cloned().unwrap_or_else(|| Ok(Poly::default()));
+        let as_const = |cell: Cell, fallback: Fallback| cell.ok().and_then(|poly| poly.as_const()).ok_or(fallback);
+        let instr = as_const(cell(&mem, ptr), Fallback::SymbolicOpcode(ptr))?;
+        let addr = |mem: &Vec<Cell>, offset: usize| -> Result<usize, Fallback> {
+            let raw = || as_const(cell(mem, ptr + offset), Fallback::SymbolicAddress(ptr));
+            let addr = match (instr / 10_i64.pow((offset + 1) as u32)) % 10 {
+                0 => raw()?,
+                1 => return Ok(ptr + offset),
+                2 => raw()?.checked_add(rel_base).ok_or(Fallback::Overflow(ptr))?,
+                _ => return Err(Fallback::UnknownOpcode(ptr)),
+            };
+            in_bounds(addr).ok_or(Fallback::BadAddress(ptr))
+        };
+        // a read through a symbolic address isn't fatal until the value is used
+        let read = |mem: &Vec<Cell>, offset: usize| addr(mem, offset).and_then(|i| cell(mem, i));
+        let store = |mem: &mut Vec<Cell>, index: usize, val: Cell| {
+            if index >= mem.len() {
+                mem.resize(index + 1, Ok(Poly::default()));
+            }
+            mem[index] = val;
+        };
+
+        match instr % 100 {
+            code @ 1 | code @ 2 | code @ 7 | code @ 8 => {
+                let a = read(&mem, 1);
+                let b = read(&mem, 2);
+                let w = addr(&mem, 3)?;
+                let res = combine(a, b, |a, b| match code {
+                    1 => a.checked_add(&b).ok_or(Fallback::Overflow(ptr)),
+                    2 => a.checked_mul(&b).ok_or(Fallback::Overflow(ptr)),
+                    // a comparison is only decidable if the difference doesn't depend on the unknowns
+                    _ => match a.checked_sub(&b).ok_or(Fallback::Overflow(ptr))?.as_const() {
+                        Some(diff) if code == 7 => Ok(Poly::constant((diff < 0) as i64)),
+                        Some(diff) => Ok(Poly::constant((diff == 0) as i64)),
+                        None => Err(Fallback::SymbolicComparison(ptr)),
+                    },
+                });
+                store(&mut mem, w, res);
+                ptr += 4;
+            }
+            3 => {
+                let w = addr(&mem, 1)?;
+                let val = *inputs.next().ok_or(Fallback::NeedsInput(ptr))?;
+                store(&mut mem, w, Ok(Poly::constant(val)));
+                ptr += 2;
+            }
+            4 => {
+                outputs.push(read(&mem, 1));
+                ptr += 2;
+            }
+            code @ 5 | code @ 6 => {
+                let a = as_const(read(&mem, 1), Fallback::SymbolicJump(ptr))?;
+                if (a != 0) == (code == 5) {
+                    let to = as_const(read(&mem, 2), Fallback::SymbolicJump(ptr))?;
+                    let to = in_bounds(to).ok_or(Fallback::BadAddress(ptr))?;
+                    // like the VM, a jump to itself carries on with the next instruction
+                    ptr = if to == ptr { ptr + 3 } else { to };
+                } else {
+                    ptr += 3;
+                }
+            }
+            9 => {
+                rel_base = rel_base.checked_add(as_const(read(&mem, 1), Fallback::SymbolicRelBase(ptr))?)
+                    .ok_or(Fallback::Overflow(ptr))?;
+                ptr += 2;
+            }
+            99 => return Ok(SymbolicRun { mem, outputs }),
+            _ => return Err(Fallback::UnknownOpcode(ptr)),
+        }
+    }
+    Err(Fallback::StepLimit)
+}
+
+#[derive(Debug, Clone)]
+pub struct Solve {
+    pub solutions: Vec<Vec<i64>>,
+    pub poly: Option<Poly>,
+    /// set when the solutions came from running every combination instead
+    pub fallback: Option<Fallback>,
+}
+
+/// Every assignment of the unknown cells, each within its range, that leaves `target` in `cell` when
+/// the program halts
+pub fn solve(mem: &[i64], unknowns: &[(usize, RangeInclusive<i64>)], cell: usize, target: i64) -> Solve {
+    let indices: Vec<usize> = unknowns.iter().map(|(index, _)| *index).collect();
+    let ranges: Vec<RangeInclusive<i64>> = unknowns.iter().map(|(_, range)| range.clone()).collect();
+    let poly = evaluate(mem, &indices, &[])
+        .and_then(|run| run.mem.get(cell).cloned().unwrap_or_else(|| Ok(Poly::default())));
+    let solved = poly.and_then(|poly| {
+        let mut solutions = Vec::new();
+        match solve_poly(&poly, 0, &ranges, target, &mut Vec::new(), &mut solutions) {
+            Some(()) => Ok((poly, solutions)),
+            // the polynomial is fine but plugging values into it overflows
+            None => Err(Fallback::OverflowSolving),
+        }
+    });
+    match solved {
+        Ok((poly, solutions)) => Solve { solutions, poly: Some(poly), fallback: None },
+        Err(fallback) => Solve {
+            solutions: brute_force(mem, &indices, &ranges, cell, target),
+            poly: None,
+            fallback: Some(fallback),
+        },
+    }
+}
+
+/// `None` if the arithmetic overflows
+fn solve_poly(poly: &Poly, i: usize, ranges: &[RangeInclusive<i64>], target: i64, vals: &mut Vec<i64>, solutions: &mut Vec<Vec<i64>>) -> Option<()> {
+    if i == ranges.len() {
+        if poly.as_const() == Some(target) {
+            solutions.push(vals.clone());
+        }
+        return Some(());
+    }
+    let range = &ranges[i];
+    if i == ranges.len() - 1 && poly.degree_in(i) <= 1 {
+        // a*x + b = target
+        let b = poly.substitute(i, 0)?.as_const().unwrap_or(0);
+        let a = poly.substitute(i, 1)?.as_const().unwrap_or(0).checked_sub(b)?;
+        let diff = target.checked_sub(b)?;
+        let xs: Vec<i64> = if a == 0 {
+            if b == target { range.clone().collect() } else { Vec::new() }
+        } else if diff.checked_rem(a)? == 0 && range.contains(&diff.checked_div(a)?) {
+            vec![diff / a]
+        } else {
+            Vec::new()
+        };
+        for x in xs {
+            vals.push(x);
+            solutions.push(vals.clone());
+            vals.pop();
+        }
+        return Some(());
+    }
+    for x in range.clone() {
+        vals.push(x);
+        solve_poly(&poly.substitute(i, x)?, i + 1, ranges, target, vals, solutions)?;
+        vals.pop();
+    }
+    Some(())
+}
+
+fn brute_force(mem: &[i64], indices: &[usize], ranges: &[RangeInclusive<i64>], cell: usize, target: i64) -> Vec<Vec<i64>> {
+    let mut solutions = Vec::new();
+    let mut vals: Vec<i64> = ranges.iter().map(|range| *range.start()).collect();
+    if ranges.iter().any(|range| range.is_empty()) {
+        return solutions;
+    }
+    let mem = mem.to_vec();
+    loop {
+        let mut com = Computer::init(&mem, empty());
+        for (&index, &val) in indices.iter().zip(&vals) {
+            if index >= com.mem.len() {
+                com.mem.resize(index + 1, 0);
+            }
+            com.mem[index] = val;
+        }
+        if halts(&mut com) && com.mem.get(cell).copied().unwrap_or(0) == target {
+            solutions.push(vals.clone());
+        }
+
+        // odometer over the ranges, last unknown fastest
+        let mut i = vals.len();
+        loop {
+            if i == 0 {
+                return solutions;
+            }
+            i -= 1;
+            if vals[i] < *ranges[i].end() {
+                vals[i] += 1;
+                break;
+            }
+            vals[i] = *ranges[i].start();
+        }
+    }
+}
+
+/// Runs until the program halts, within the step limit. Arbitrary values can send it anywhere, so
+/// anything the VM can't run safely, or that overflows, counts as not halting.
+fn halts(com: &mut Computer) -> bool {
+    let mut from = 0;
+    while com.steps() < STEP_LIMIT as u64 {
+        if com.check_step(from, MEM_LIMIT).is_err() {
+            return false;
+        }
+        from = com.ptr;
+        match panic::catch_unwind(AssertUnwindSafe(|| com.step())) {
+            Ok(true) => {}
+            Ok(false) => return com.is_done,
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{solve, Fallback, Poly};
+    use crate::intcode::Computer;
+
+    #[test]
+    fn solves_day2_without_running_it() {
+        let mem = Computer::load(include_str!("../../input/2019/day2.txt")).unwrap();
+        let found = solve(&mem, &[(1, 0..=99), (2, 0..=99)], 0, 19690720);
+        assert_eq!(found.fallback, None);
+        assert_eq!(found.solutions, vec![vec![64, 29]]);
+        let poly = found.poly.unwrap();
+        let mut com = Computer::init(&mem, std::iter::empty());
+        com.mem[1] = 12;
+        com.mem[2] = 2;
+        com.compute();
+        assert_eq!(poly.eval(&[12, 2]), Some(com.mem[0]));
+    }
+
+    #[test]
+    fn poly_arithmetic_is_checked() {
+        let x = Poly::var(0);
+        let big = Poly::constant(i64::MAX);
+        assert_eq!(x.checked_mul(&big).unwrap().eval(&[1]), Some(i64::MAX));
+        assert_eq!(x.checked_mul(&big).unwrap().eval(&[2]), None);
+        assert_eq!(big.checked_add(&Poly::constant(1)), None);
+        assert_eq!(Poly::constant(i64::MIN).checked_neg(), None);
+        assert_eq!(x.checked_sub(&x).unwrap().as_const(), Some(0));
+    }
+
+    #[test]
+    fn overflow_falls_back_to_running_it() {
+        // mem[0] = mem[9] * mem[10] * 2, the second product overflows as a polynomial
+        let mem = vec![2, 9, 10, 0, 1002, 0, 2, 0, 99, 0, i64::MAX / 2 + 1];
+        let found = solve(&mem, &[(9, 0..=3)], 0, 0);
+        assert_eq!(found.fallback, Some(Fallback::Overflow(4)));
+        // anything but 0 overflows the VM too
+        assert_eq!(found.solutions, vec![vec![0]]);
+    }
+
+    #[test]
+    fn garbage_values_stop_the_run_instead_of_the_vm() {
+        // jumps to whatever the unknown is, most of which isn't code. A jump to itself moves on like any
+        // other instruction, so 0 halts too.
+        let mem = vec![1105, 1, 0, 99];
+        let found = solve(&mem, &[(2, -5..=10)], 0, 1105);
+        assert_eq!(found.fallback, Some(Fallback::SymbolicJump(0)));
+        assert_eq!(found.solutions, vec![vec![0], vec![3]]);
+    }
+
+    #[test]
+    fn addresses_outside_memory_fall_back() {
+        // mem[-5] = mem[9] + 1; mem[1 << 40] = 1
+        for &w in &[-5, 1 << 40] {
+            let mem = vec![101, 1, 9, w, 99, 0, 0, 0, 0, 0];
+            let found = solve(&mem, &[(9, 0..=3)], 0, 0);
+            assert_eq!(found.fallback, Some(Fallback::BadAddress(0)));
+            assert!(found.solutions.is_empty());
+        }
+    }
+
+    #[test]
+    fn a_jump_to_itself_moves_on() {
+        // jnz 1 0; mem[0] = mem[9] * 2; halt
+        let mem = vec![1105, 1, 0, 1002, 9, 2, 0, 99, 0, 0];
+        let found = solve(&mem, &[(9, 0..=10)], 0, 14);
+        assert_eq!(found.fallback, None);
+        assert_eq!(found.solutions, vec![vec![7]]);
+    }
+}
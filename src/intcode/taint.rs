@@ -0,0 +1,241 @@
+use crate::intcode::{Computer, Opcode, Mode};
+use std::convert::TryFrom;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+type Set = Option<Arc<BTreeSet<usize>>>;
+
+fn union(a: &Set, b: &Set) -> Set {
+    match (a, b) {
+        (None, set) | (set, None) => set.clone(),
+        (Some(a), Some(b)) if Arc::ptr_eq(a, b) || b.is_subset(a) => Some(a.clone()),
+        (Some(a), Some(b)) if a.is_subset(b) => Some(b.clone()),
+        (Some(a), Some(b)) => Some(Arc::new(a.union(b).copied().collect())),
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Flow {
+    /// only values computed from an input carry its id
+    Data,
+    /// also whatever runs after a jump on a tainted value, until the branch is over: in the same frame,
+    /// execution reaches the further of the jump's target and the instruction after it. That's where an
+    /// `if` or a loop rejoins, though the `else` arm of an `if`/`else` falls outside it
+    Control,
+}
+
+/// An output and the ids of the inputs it depends on, inputs are numbered from 0 in the order they
+/// were read
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TaintedOutput {
+    pub val: i64,
+    pub step: u64,
+    pub sources: Vec<usize>,
+}
+
+// a branch on a tainted value that hasn't rejoined yet
+#[derive(Debug)]
+struct Scope {
+    set: Set,
+    end: usize,
+    rel_base: i64,
+}
+
+#[derive(Debug)]
+pub(super) struct Taint {
+    flow: Flow,
+    cells: Vec<Set>,
+    inputs: usize,
+    scopes: Vec<Scope>,
+    // what the relative base was computed from, relative operands are addressed by it
+    rel_base: Set,
+    outputs: Vec<TaintedOutput>,
+}
+
+impl Taint {
+    fn get(&self, mode: &Mode) -> Set {
+        match mode {
+            Mode::Imm(_) => None,
+            Mode::Pos(index) => self.cells.get(*index).cloned().flatten(),
+            Mode::Rel(index) => union(&self.cells.get(*index).cloned().flatten(), &self.rel_base),
+        }
+    }
+
+    fn set(&mut self, mode: &Mode, set: Set) {
+        let index = mode.index();
+        if index >= self.cells.len() {
+            self.cells.resize(index + 1, None);
+        }
+        let mut set = union(&set, &self.control());
+        if let Mode::Rel(_) = mode {
+            set = union(&set, &self.rel_base);
+        }
+        self.cells[index] = set;
+    }
+
+    /// Forgets every input and output, for when the program starts over
+    pub(super) fn clear(&mut self, len: usize) {
+        self.cells.clear();
+        self.cells.resize(len, None);
+        self.inputs = 0;
+        self.scopes.clear();
+        self.rel_base = None;
+        self.outputs.clear();
+    }
+
+    /// `mem[index]` was set from outside the program, so it no longer depends on any input
+    pub(super) fn untaint(&mut self, index: usize) {
+        if let Some(cell) = self.cells.get_mut(index) {
+            *cell = None;
+        }
+    }
+
+    fn control(&self) -> Set {
+        self.scopes.iter().fold(None, |acc, scope| union(&acc, &scope.set))
+    }
+
+    /// Opens a branch on `set` for a jump at `ptr` to `target`, then closes the ones execution has
+    /// left by going to `next`
+    fn branch(&mut self, set: Set, ptr: usize, width: usize, target: usize, next: usize, rel_base: i64) {
+        if self.flow == Flow::Control && set.is_some() {
+            let end = target.max(ptr + width);
+            self.scopes.push(Scope { set, end, rel_base });
+        }
+        self.scopes.retain(|scope| scope.rel_base != rel_base || next < scope.end);
+    }
+}
+
+impl Computer {
+    /// Tags every input read from now on and follows it through to the outputs
+    pub fn track_taint(&mut self, flow: Flow) {
+        self.taint = Some(Taint {
+            flow,
+            cells: vec![None; self.mem.len()],
+            inputs: 0,
+            scopes: Vec::new(),
+            rel_base: None,
+            outputs: Vec::new(),
+        });
+    }
+
+    pub fn stop_taint(&mut self) -> Vec<TaintedOutput> {
+        self.taint.take().map_or_else(Vec::new, |taint| taint.outputs)
+    }
+
+    pub fn tainted_outputs(&self) -> &[TaintedOutput] {
+        self.taint.as_ref().map_or(&[], |taint| &taint.outputs)
+    }
+
+    /// The inputs the value at `index` currently depends on
+    pub fn taint_of(&self, index: usize) -> Vec<usize> {
+        self.taint.as_ref()
+            .and_then(|taint| taint.cells.get(index).cloned().flatten())
+            .map_or_else(Vec::new, |set| set.iter().copied().collect())
+    }
+
+    /// Carries taint through `opcode`, which has just run from `ptr`
+    pub(super) fn propagate(&mut self, opcode: &Opcode, ptr: usize) {
+        let width = opcode.nparams() + opcode.nwrites() + 1;
+        let jumped = self.ptr != ptr;
+        let next = if jumped { self.ptr } else { ptr + width };
+        let taint = self.taint.as_mut().unwrap();
+        let mut branch = (None, next);
+        match opcode {
+            Opcode::Add(a, b, w) | Opcode::Mult(a, b, w) | Opcode::Less(a, b, w) | Opcode::Equal(a, b, w) => {
+                let set = union(&taint.get(a), &taint.get(b));
+                taint.set(w, set);
+            }
+            Opcode::Input(w) => {
+                let id = taint.inputs;
+                taint.inputs += 1;
+                taint.set(w, Some(Arc::new(Some(id).into_iter().collect())));
+            }
+            Opcode::Output(a) => {
+                let set = union(&taint.get(a), &taint.control());
+                taint.outputs.push(TaintedOutput {
+                    val: self.output.back().copied().unwrap_or(0),
+                    step: self.steps,
+                    sources: set.map_or_else(Vec::new, |set| set.iter().copied().collect()),
+                });
+            }
+            Opcode::JumpNZero(c, j) | Opcode::JumpZero(c, j) => {
+                let mut set = taint.get(c);
+                if jumped {
+                    set = union(&set, &taint.get(j));
+                }
+                let target = self.mem.get(j.index()).copied().unwrap_or(0);
+                branch = (set, usize::try_from(target).unwrap_or(usize::MAX));
+            }
+            Opcode::SetRelBase(a) => {
+                let set = taint.get(a);
+                taint.rel_base = union(&taint.rel_base, &set);
+            }
+            Opcode::Halt | Opcode::Ext(_) => {}
+        }
+        taint.branch(branch.0, ptr, width, branch.1, next, self.rel_base);
+    }
+
+    /// Carries taint through an extension instruction at `ptr` that's about to continue at `next`.
+    /// Only writes made through its write operands are seen, each taking the taint of all the read
+    /// operands, and it only counts as a branch when it actually jumps
+    pub(super) fn propagate_ext(&mut self, reads: &[Mode], writes: &[Mode], next: usize) {
+        let ptr = self.ptr;
+        let taint = self.taint.as_mut().unwrap();
+        let set = reads.iter().fold(None, |acc, mode| union(&acc, &taint.get(mode)));
+        for mode in writes {
+            taint.set(mode, set.clone());
+        }
+        let width = reads.len() + writes.len() + 1;
+        let branch = if next != ptr + width { set } else { None };
+        taint.branch(branch, ptr, width, next, next, self.rel_base);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Flow;
+    use crate::intcode::Computer;
+    use crate::intcode::ext::{Extension, Effect};
+
+    fn sources(com: &Computer) -> Vec<(i64, Vec<usize>)> {
+        com.tainted_outputs().iter().map(|out| (out.val, out.sources.clone())).collect()
+    }
+
+    #[test]
+    fn control_taint_lasts_until_the_branch_rejoins() {
+        // in [100]; jz [100] 11; in [101]; out 7; out 8; out 9; halt
+        let prog = [3, 100, 1006, 100, 11, 3, 101, 104, 7, 104, 8, 104, 9, 99];
+        let mut com = Computer::init(&prog, vec![1, 5]);
+        com.track_taint(Flow::Control);
+        com.compute();
+        assert_eq!(sources(&com), vec![(7, vec![0]), (8, vec![0]), (9, vec![])]);
+
+        let mut com = Computer::init(&prog, vec![0]);
+        com.track_taint(Flow::Control);
+        com.compute();
+        assert_eq!(sources(&com), vec![(9, vec![])]);
+    }
+
+    #[test]
+    fn relative_operands_carry_the_base_taint() {
+        // in [100]; rel += [100]; out rel[0]; halt
+        let mut com = Computer::init(&[3, 100, 9, 100, 204, 0, 99], vec![3]);
+        com.track_taint(Flow::Data);
+        com.compute();
+        assert_eq!(sources(&com), vec![(100, vec![0])]);
+    }
+
+    #[test]
+    fn extension_writes_take_the_read_taint() {
+        // in [100]; double [100] -> [101]; out [101]; halt
+        let mut com = Computer::init(&[3, 100, 50, 100, 101, 4, 101, 99], vec![4]);
+        com.register(Extension::new(50, "double", 1, 1, |com, args| {
+            let val = args.read(com, 0);
+            args.write(com, 1, val * 2);
+            Effect::Next
+        }));
+        com.track_taint(Flow::Data);
+        com.compute();
+        assert_eq!(sources(&com), vec![(8, vec![0])]);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::intcode::Computer;
+use crate::intcode::calls::CallStack;
+use crate::intcode::decode::decode;
+use crate::intcode::json::Json;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter, Error};
+use std::fs;
+use std::path::Path;
+
+// instructions kept for the trace shown with a failure
+const TRACE: usize = 12;
+const MEM_LIMIT: usize = 1 << 24;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stop {
+    Halt,
+    Input,
+    StepLimit,
+    Crash,
+}
+
+impl Stop {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "halt" => Some(Stop::Halt),
+            "input" => Some(Stop::Input),
+            "steps" => Some(Stop::StepLimit),
+            "crash" => Some(Stop::Crash),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Stop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", match self {
+            Stop::Halt => "halt",
+            Stop::Input => "input",
+            Stop::StepLimit => "steps",
+            Stop::Crash => "crash",
+        })
+    }
+}
+
+/// One test, read from JSON like
+///
+/// ```text
+/// {
+///     "name": "day2 example",
+///     "program": "1,9,10,3,2,3,11,0,99,30,40,50",
+///     "patches": [[1, 9]],
+///     "input": [],
+///     "output": [],
+///     "memory": [[0, 3500]],
+///     "stop": "halt",
+///     "steps": 1000
+/// }
+/// ```
+///
+/// `path` can stand in for `program`, relative to the test file. Everything but the program is
+/// optional, and expectations that are left out aren't checked. `stop` is one of `halt`, `input`
+/// (blocked waiting for more), `steps` (hit the `steps` limit) or `crash`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub patches: Vec<(usize, i64)>,
+    pub input: Vec<i64>,
+    pub output: Option<Vec<i64>>,
+    pub memory: Vec<(usize, i64)>,
+    pub stop: Option<Stop>,
+    pub steps: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Failure {
+    pub name: String,
+    pub problems: Vec<String>,
+    /// the last instructions run, oldest first
+    pub trace: Vec<String>,
+    /// the calls the program was in when it stopped
+    pub stack: CallStack,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        writeln!(f, "{} failed:", self.name)?;
+        for problem in &self.problems {
+            writeln!(f, "    {}", problem)?;
+        }
+        writeln!(f, "  last instructions:")?;
+        for line in &self.trace {
+            writeln!(f, "    {}", line)?;
+        }
+        if self.stack.depth() > 0 {
+            writeln!(f, "  call stack:")?;
+            for line in self.stack.to_string().lines() {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn ints(json: Option<&Json>, field: &str) -> Result<Vec<i64>, String> {
+    match json {
+        None => Ok(Vec::new()),
+        Some(json) => json.as_array()
+            .and_then(|vals| vals.iter().map(Json::as_i64).collect())
+            .ok_or_else(|| format!("`{}` should be a list of integers", field)),
+    }
+}
+
+fn cells(json: Option<&Json>, field: &str) -> Result<Vec<(usize, i64)>, String> {
+    let err = || format!("`{}` should be a list of [address, value] pairs", field);
+    match json {
+        None => Ok(Vec::new()),
+        Some(json) => json.as_array().ok_or_else(err)?
+            .iter()
+            .map(|pair| match pair.as_array() {
+                Some([addr, val]) => match (addr.as_i64(), val.as_i64()) {
+                    (Some(addr), Some(val)) if addr >= 0 => Ok((addr as usize, val)),
+                    _ => Err(err()),
+                },
+                _ => Err(err()),
+            })
+            .collect(),
+    }
+}
+
+impl Case {
+    /// `dir` is where `path` is relative to
+    pub fn from_json(json: &Json, dir: &Path, default_name: String) -> Result<Self, String> {
+        let name = json.get("name").and_then(Json::as_str).map_or(default_name, str::to_string);
+        let in_case = |err: String| format!("{}: {}", name, err);
+        let program = match (json.get("program").and_then(Json::as_str), json.get("path").and_then(Json::as_str)) {
+            (Some(src), _) => Computer::load(src).map_err(|err| in_case(format!("program: {}", err)))?,
+            (None, Some(path)) => {
+                let path = dir.join(path);
+                let src = fs::read_to_string(&path).map_err(|err| in_case(format!("{}: {}", path.display(), err)))?;
+                Computer::load(&src).map_err(|err| in_case(format!("{}:{}", path.display(), err)))?
+            }
+            (None, None) => return Err(in_case("needs a `program` or a `path`".to_string())),
+        };
+        let stop = match json.get("stop") {
+            None => None,
+            Some(stop) => Some(stop.as_str().and_then(Stop::parse)
+                .ok_or_else(|| in_case("`stop` should be one of halt, input, steps or crash".to_string()))?),
+        };
+        let output = match json.get("output") {
+            None => None,
+            output => Some(ints(output, "output").map_err(in_case)?),
+        };
+        Ok(Case {
+            program,
+            patches: cells(json.get("patches"), "patches").map_err(in_case)?,
+            input: ints(json.get("input"), "input").map_err(in_case)?,
+            output,
+            memory: cells(json.get("memory"), "memory").map_err(in_case)?,
+            stop,
+            steps: json.get("steps").and_then(Json::as_i64).map_or(10_000_000, |n| n.max(0) as u64),
+            name,
+        })
+    }
+
+    /// A file holds one case or a list of them
+    pub fn load(path: &Path) -> Result<Vec<Self>, String> {
+        let src = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let json = Json::parse(&src).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let stem = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        match json.as_array() {
+            Some(cases) => cases.iter()
+                .enumerate()
+                .map(|(i, case)| Case::from_json(case, dir, format!("{}[{}]", stem, i)))
+                .collect(),
+            None => Ok(vec![Case::from_json(&json, dir, stem)?]),
+        }
+    }
+
+    /// Every case in the `.json` files in `dir`, in file name order
+    pub fn load_dir(dir: &Path) -> Result<Vec<Self>, String> {
+        let mut paths = fs::read_dir(dir)
+            .map_err(|err| format!("{}: {}", dir.display(), err))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        let mut cases = Vec::new();
+        for path in paths {
+            cases.extend(Case::load(&path)?);
+        }
+        Ok(cases)
+    }
+
+    pub fn run(&self) -> Result<(), Failure> {
+        let mut mem = self.program.clone();
+        for &(index, val) in &self.patches {
+            if index >= mem.len() {
+                mem.resize(index + 1, 0);
+            }
+            mem[index] = val;
+        }
+        let mut com = Computer::init(&mem, self.input.iter().copied());
+        com.track_calls();
+        let mut trace = VecDeque::with_capacity(TRACE);
+        let mut crash = None;
+        let mut from = 0;
+        let stop = loop {
+            if com.steps >= self.steps {
+                break Stop::StepLimit;
+            }
+            if let Err(err) = com.check_step(from, MEM_LIMIT) {
+                crash = Some(err);
+                break Stop::Crash;
+            }
+            from = com.ptr;
+            if trace.len() == TRACE {
+                trace.pop_front();
+            }
+            trace.push_back(match decode(&com.mem, com.ptr) {
+                Some(instr) => format!("{:>6}: {}", com.ptr, instr),
+                None => format!("{:>6}: {}", com.ptr, com.mem[com.ptr]),
+            });
+            if !com.step() {
+                break if com.is_done { Stop::Halt } else { Stop::Input };
+            }
+        };
+
+        let mut problems = Vec::new();
+        if let Some(crash) = crash {
+            if self.stop != Some(Stop::Crash) {
+                problems.push(format!("crashed: {}", crash));
+            }
+        }
+        if let Some(expected) = self.stop {
+            if expected != stop {
+                problems.push(format!("stopped on {}, expected {}", stop, expected));
+            }
+        }
+        let output: Vec<i64> = com.recv_all().collect();
+        if let Some(expected) = &self.output {
+            if *expected != output {
+                problems.push(format!("output {:?}, expected {:?}", output, expected));
+            }
+        }
+        for &(index, expected) in &self.memory {
+            let found = com.mem.get(index).copied().unwrap_or(0);
+            if found != expected {
+                problems.push(format!("[{}] is {}, expected {}", index, found, expected));
+            }
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(Failure {
+                name: self.name.clone(),
+                problems,
+                trace: trace.into(),
+                stack: com.stop_tracking_calls().unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Case;
+    use std::path::Path;
+
+    #[test]
+    fn intcode_tests_pass() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("intcode-tests");
+        let cases = Case::load_dir(&dir).unwrap();
+        assert!(!cases.is_empty());
+        let failures: Vec<String> = cases.iter()
+            .filter_map(|case| case.run().err())
+            .map(|failure| failure.to_string())
+            .collect();
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,329 @@
+use crate::intcode::{Computer, reset, state};
+use crate::intcode::decode::{decode, Instr, Param};
+
+const UNOWNED: usize = usize::MAX;
+
+#[derive(Debug, Copy, Clone)]
+enum Src {
+    Const(i64),
+    Pos(usize),
+    Rel(i64),
+}
+
+impl Src {
+    fn from(param: Param) -> Self {
+        match param {
+            Param::Pos(i) => Src::Pos(i as usize),
+            Param::Imm(n) => Src::Const(n),
+            Param::Rel(off) => Src::Rel(off),
+        }
+    }
+
+    #[inline(always)]
+    fn get(self, com: &Computer) -> i64 {
+        match self {
+            Src::Const(n) => n,
+            Src::Pos(i) => *com.mem.get(i).unwrap_or(&0),
+            Src::Rel(off) => *com.mem.get((com.rel_base + off) as usize).unwrap_or(&0),
+        }
+    }
+
+    #[inline(always)]
+    fn index(self, com: &Computer) -> usize {
+        match self {
+            Src::Pos(i) => i,
+            Src::Rel(off) => (com.rel_base + off) as usize,
+            // decode never hands out immediate writes
+            Src::Const(_) => unreachable!(),
+        }
+    }
+}
+
+#[inline(always)]
+fn store(com: &mut Computer, index: usize, val: i64) {
+    if index >= com.mem.len() {
+        com.mem.resize(index + 1, 0);
+    }
+    state::rewrite(&mut com.fingerprint, index, com.mem[index], val);
+    reset::touch(&mut com.pristine, index);
+    com.mem[index] = val;
+}
+
+enum Step {
+    Next,
+    Wrote(usize),
+    Wait,
+    Halt,
+}
+
+type Exec = fn(&Op, &mut Computer) -> Step;
+
+struct Op {
+    exec: Exec,
+    a: Src,
+    b: Src,
+    w: Src,
+    addr: usize,
+    next: usize,
+}
+
+enum Slot {
+    Empty,
+    Op(Op),
+    Interpret,
+}
+
+fn add(op: &Op, com: &mut Computer) -> Step {
+    let index = op.w.index(com);
+    store(com, index, op.a.get(com) + op.b.get(com));
+    com.ptr = op.next;
+    Step::Wrote(index)
+}
+
+fn mult(op: &Op, com: &mut Computer) -> Step {
+    let index = op.w.index(com);
+    store(com, index, op.a.get(com) * op.b.get(com));
+    com.ptr = op.next;
+    Step::Wrote(index)
+}
+
+fn input(op: &Op, com: &mut Computer) -> Step {
+    match com.input.pop_front() {
+        Some(val) => {
+            let index = op.w.index(com);
+            store(com, index, val);
+            com.ptr = op.next;
+            Step::Wrote(index)
+        }
+        None => Step::Wait,
+    }
+}
+
+fn output(op: &Op, com: &mut Computer) -> Step {
+    let val = op.a.get(com);
+    com.output.push_back(val);
+    if !com.observers.is_empty() {
+        com.observers.notify(val);
+    }
+    com.ptr = op.next;
+    Step::Next
+}
+
+/// Like the interpreter, a jump to the jump itself carries on with the next instruction
+#[inline(always)]
+fn jump(op: &Op, com: &mut Computer, taken: bool) -> Step {
+    com.ptr = match op.b.get(com) as usize {
+        target if taken && target != op.addr => target,
+        _ => op.next,
+    };
+    Step::Next
+}
+
+fn jump_nzero(op: &Op, com: &mut Computer) -> Step {
+    let taken = op.a.get(com) != 0;
+    jump(op, com, taken)
+}
+
+fn jump_zero(op: &Op, com: &mut Computer) -> Step {
+    let taken = op.a.get(com) == 0;
+    jump(op, com, taken)
+}
+
+fn less(op: &Op, com: &mut Computer) -> Step {
+    let index = op.w.index(com);
+    store(com, index, (op.a.get(com) < op.b.get(com)) as i64);
+    com.ptr = op.next;
+    Step::Wrote(index)
+}
+
+fn equal(op: &Op, com: &mut Computer) -> Step {
+    let index = op.w.index(com);
+    store(com, index, (op.a.get(com) == op.b.get(com)) as i64);
+    com.ptr = op.next;
+    Step::Wrote(index)
+}
+
+fn set_rel_base(op: &Op, com: &mut Computer) -> Step {
+    com.rel_base += op.a.get(com);
+    com.ptr = op.next;
+    Step::Next
+}
+
+fn halt(_: &Op, _: &mut Computer) -> Step {
+    Step::Halt
+}
+
+/// Runs a `Computer` from instructions that are each decoded once into a function pointer with its
+/// operands already resolved. An instruction that the program writes over is run by the interpreter
+/// from then on.
+pub struct Threaded {
+    slots: Vec<Slot>,
+    // the start of the compiled instruction each address is part of
+    owner: Vec<usize>,
+}
+
+impl Default for Threaded {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Threaded {
+    pub fn new() -> Self {
+        Threaded {
+            slots: Vec::new(),
+            owner: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self, com: &mut Computer) {
+        // hooks, history and extensions all live in the interpreter
+        if !com.regions.is_empty() || com.history.is_some() || !com.extensions.is_empty() || com.session.is_some()
+            || com.calls.is_some() || com.strict.is_some() || com.taint.is_some() || com.usage.is_some() {
+            com.compute();
+            return;
+        }
+
+        loop {
+            let ptr = com.ptr;
+            if ptr >= self.slots.len() {
+                self.slots.resize_with(ptr + 1, || Slot::Empty);
+            }
+            if let Slot::Empty = self.slots[ptr] {
+                self.compile(com, ptr);
+            }
+            let step = match &self.slots[ptr] {
+                Slot::Op(op) => {
+                    let step = (op.exec)(op, com);
+                    match step {
+                        Step::Wait => {}
+                        _ => com.steps += 1,
+                    }
+                    step
+                }
+                _ => {
+                    let wrote = decode(&com.mem, ptr)
+                        .and_then(|instr| instr.write())
+                        .map(|param| Src::from(param).index(com));
+                    if !com.step() {
+                        return;
+                    }
+                    wrote.map_or(Step::Next, Step::Wrote)
+                }
+            };
+            match step {
+                Step::Next => {}
+                Step::Wrote(index) => if index < self.owner.len() && self.owner[index] != UNOWNED {
+                    self.invalidate(self.owner[index]);
+                },
+                Step::Wait => return,
+                Step::Halt => {
+                    com.is_done = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn compile(&mut self, com: &Computer, ptr: usize) {
+        let instr = match decode(&com.mem, ptr) {
+            Some(instr) => instr,
+            None => {
+                self.slots[ptr] = Slot::Interpret;
+                return;
+            }
+        };
+        if instr.next() > self.owner.len() {
+            self.owner.resize(instr.next(), UNOWNED);
+        }
+        // a jump into the middle of another instruction, leave the overlap to the interpreter
+        if self.owner[ptr..instr.next()].iter().any(|&owner| owner != UNOWNED) {
+            self.slots[ptr] = Slot::Interpret;
+            return;
+        }
+        for owner in &mut self.owner[ptr..instr.next()] {
+            *owner = ptr;
+        }
+        self.slots[ptr] = Slot::Op(Op::from(&instr));
+    }
+
+    fn invalidate(&mut self, start: usize) {
+        let next = match &self.slots[start] {
+            Slot::Op(op) => op.next,
+            _ => return,
+        };
+        for owner in &mut self.owner[start..next] {
+            *owner = UNOWNED;
+        }
+        self.slots[start] = Slot::Interpret;
+    }
+}
+
+impl Op {
+    fn from(instr: &Instr) -> Self {
+        let exec: Exec = match instr.code {
+            1 => add,
+            2 => mult,
+            3 => input,
+            4 => output,
+            5 => jump_nzero,
+            6 => jump_zero,
+            7 => less,
+            8 => equal,
+            9 => set_rel_base,
+            _ => halt,
+        };
+        let [a, b, w] = instr.params;
+        let (a, b, w) = match instr.code {
+            3 => (Src::Const(0), Src::Const(0), Src::from(a)),
+            _ => (Src::from(a), Src::from(b), Src::from(w)),
+        };
+        Op { exec, a, b, w, addr: instr.addr, next: instr.next() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Threaded;
+    use crate::intcode::Computer;
+
+    /// Runs `mem` on `inputs` in the interpreter and as threaded code, and checks they agree
+    fn check(mem: &[i64], inputs: &[i64]) -> Vec<i64> {
+        let mut slow = Computer::init(mem, inputs.iter().copied());
+        slow.compute();
+        let mut fast = Computer::init(mem, inputs.iter().copied());
+        Threaded::new().run(&mut fast);
+        assert_eq!(fast.mem, slow.mem);
+        assert_eq!((fast.ptr, fast.is_done, fast.steps), (slow.ptr, slow.is_done, slow.steps));
+        let output = slow.recv_all().collect::<Vec<_>>();
+        assert_eq!(fast.recv_all().collect::<Vec<_>>(), output);
+        output
+    }
+
+    #[test]
+    fn days() {
+        check(&Computer::load(include_str!("../../input/2019/day5.txt")).unwrap(), &[5]);
+        check(&Computer::load(include_str!("../../input/2019/day9.txt")).unwrap(), &[1]);
+        check(&Computer::load(include_str!("../../input/2019/day13.txt")).unwrap(), &[]);
+    }
+
+    #[test]
+    fn jumps_to_themselves_move_on() {
+        assert_eq!(check(&[1105, 1, 0, 104, 5, 99], &[]), vec![5]);
+        assert_eq!(check(&[1106, 0, 0, 104, 6, 99], &[]), vec![6]);
+    }
+
+    #[test]
+    fn rewritten_instructions_run_as_rewritten() {
+        // a loop that prints an immediate and bumps it each time round
+        let mem = vec![104, 0, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 1101, 0, 99, 0, 99, 99, 99, 0];
+        assert_eq!(check(&mem, &[]), vec![0, 1, 2]);
+        // an output that's turned into a halt once it's run
+        assert_eq!(check(&[104, 1, 1101, 0, 99, 0, 1105, 1, 0], &[]), vec![1]);
+    }
+
+    #[test]
+    fn waits_for_input_where_the_interpreter_does() {
+        check(&[3, 9, 4, 9, 3, 10, 4, 10, 99, 0, 0], &[7]);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::intcode::decode::{decode, discover, trace, Instr, Param};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter, Error, Write};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TranspileError {
+    NoCode,
+    /// the instruction at `ptr` writes over the opcode or jump operand at `target`
+    SelfModifying { ptr: usize, target: usize },
+}
+
+impl Display for TranspileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            TranspileError::NoCode => write!(f, "there's no instruction at address 0"),
+            TranspileError::SelfModifying { ptr, target } => write!(f, "the instruction at {} writes to code at {}", ptr, target),
+        }
+    }
+}
+
+impl std::error::Error for TranspileError {}
+
+/// An operand the program writes over is read from its word in memory at `live` each time
+fn read(param: Param, live: Option<usize>) -> String {
+    match (param, live) {
+        (Param::Pos(i), None) => format!("self.read({})", i),
+        (Param::Imm(n), None) => n.to_string(),
+        (Param::Rel(off), None) => format!("self.read({})", rel(off)),
+        (Param::Pos(_), Some(word)) => format!("self.read(self.mem[{}])", word),
+        (Param::Imm(_), Some(word)) => format!("self.mem[{}]", word),
+        (Param::Rel(_), Some(word)) => format!("self.read(self.rb + self.mem[{}])", word),
+    }
+}
+
+fn index(param: Param, live: Option<usize>) -> String {
+    match (param, live) {
+        (Param::Pos(i), None) => i.to_string(),
+        (Param::Rel(off), None) => rel(off),
+        (Param::Pos(_), Some(word)) => format!("self.mem[{}]", word),
+        (Param::Rel(_), Some(word)) => format!("self.rb + self.mem[{}]", word),
+        (Param::Imm(_), _) => unreachable!(),
+    }
+}
+
+fn rel(off: i64) -> String {
+    match off {
+        0 => "self.rb".to_string(),
+        off if off < 0 => format!("self.rb - {}", -off),
+        off => format!("self.rb + {}", off),
+    }
+}
+
+fn arm(out: &mut String, instr: &Instr, live: &BTreeSet<usize>) -> Result<(), Error> {
+    let word = |i: usize| Some(instr.addr + 1 + i).filter(|word| live.contains(word));
+    let read = |i: usize| read(instr.params[i], word(i));
+    let index = |i: usize| index(instr.params[i], word(i));
+    let [_, b, _] = instr.params;
+    let next = instr.next();
+    writeln!(out, "                {} => {{ // {}", instr.addr, instr)?;
+    match instr.code {
+        1 | 2 | 7 | 8 => {
+            let expr = match instr.code {
+                1 => format!("{} + {}", read(0), read(1)),
+                2 => format!("{} * {}", read(0), read(1)),
+                7 => format!("({} < {}) as i64", read(0), read(1)),
+                _ => format!("({} == {}) as i64", read(0), read(1)),
+            };
+            writeln!(out, "                    let val = {};", expr)?;
+            writeln!(out, "                    self.write({}, val);", index(2))?;
+            writeln!(out, "                    self.pc = {};", next)?;
+        }
+        3 => {
+            writeln!(out, "                    let val = match input() {{")?;
+            writeln!(out, "                        Some(val) => val,")?;
+            writeln!(out, "                        None => return false,")?;
+            writeln!(out, "                    }};")?;
+            writeln!(out, "                    self.write({}, val);", index(0))?;
+            writeln!(out, "                    self.pc = {};", next)?;
+        }
+        4 => {
+            writeln!(out, "                    output({});", read(0))?;
+            writeln!(out, "                    self.pc = {};", next)?;
+        }
+        // like the VM, a jump to the jump itself carries on with the next instruction
+        5 | 6 => {
+            let cond = if instr.code == 5 { "!=" } else { "==" };
+            match b {
+                Param::Imm(target) if target as usize == instr.addr => {
+                    writeln!(out, "                    self.pc = {};", next)?;
+                }
+                Param::Imm(target) => {
+                    writeln!(out, "                    self.pc = if {} {} 0 {{ {} }} else {{ {} }};", read(0), cond, target, next)?;
+                }
+                _ => {
+                    writeln!(out, "                    let to = {} as usize;", read(1))?;
+                    writeln!(out, "                    self.pc = if {} {} 0 && to != {} {{ to }} else {{ {} }};", read(0), cond, instr.addr, next)?;
+                }
+            }
+        }
+        9 => {
+            writeln!(out, "                    self.rb += {};", read(0))?;
+            writeln!(out, "                    self.pc = {};", next)?;
+        }
+        _ => {
+            writeln!(out, "                    return true;")?;
+        }
+    }
+    writeln!(out, "                }}")
+}
+
+/// Writes a standalone Rust module that runs `mem` as a state machine with a jump table over its
+/// instructions. `Machine::run` takes the input and output functions and, like `Computer::compute`,
+/// returns once the program halts (`true`) or needs input it doesn't have (`false`).
+///
+/// Entry points are address 0 plus any immediate pointing just past a jump, which covers the return
+/// addresses that compiled programs push. Operands of anything but jumps that the program writes
+/// to by address are read from memory when they're used. Any other write to code is turned down
+/// here if it's static and stops the machine if it only happens at runtime.
+pub fn transpile(mem: &[i64]) -> Result<String, TranspileError> {
+    decode(mem, 0).ok_or(TranspileError::NoCode)?;
+    let mut roots = vec![0];
+    let mut code = trace(mem, &roots);
+    loop {
+        // a pushed return address is an immediate pointing just past the jump that made the call
+        let after_jumps: BTreeSet<usize> = code.values()
+            .filter(|instr| instr.is_jump())
+            .map(|instr| instr.next())
+            .collect();
+        let more: BTreeSet<usize> = code.values()
+            .flat_map(|instr| instr.params().iter().copied())
+            .filter_map(|param| match param {
+                Param::Imm(n) if n >= 0 => Some(n as usize),
+                _ => None,
+            })
+            .filter(|n| after_jumps.contains(n) && !roots.contains(n))
+            .collect();
+        if more.is_empty() {
+            break;
+        }
+        roots.extend(more);
+        code = trace(mem, &roots);
+    }
+
+    // include where execution goes next even when that doesn't decode yet, it may be patched first
+    let mut words: BTreeSet<usize> = code.values()
+        .flat_map(|instr| (instr.addr..instr.next()).chain(instr.successors()))
+        .collect();
+    // code that overlaps other code may well never run, so its writes are only checked at runtime
+    let sure = discover(mem, &roots);
+    let mut live = BTreeSet::new();
+    for instr in sure.values() {
+        let target = match instr.write() {
+            Some(Param::Pos(target)) if words.contains(&(target as usize)) => target as usize,
+            _ => continue,
+        };
+        // overlapping instructions each get their own arm, so every one the word is part of counts
+        let mut owners = code.range(target.saturating_sub(3)..=target).map(|(_, owner)| owner)
+            .filter(|owner| target < owner.next())
+            .peekable();
+        if owners.peek().is_none() || owners.any(|owner| owner.addr == target || owner.is_jump()) {
+            return Err(TranspileError::SelfModifying { ptr: instr.addr, target });
+        }
+        live.insert(target);
+    }
+    for word in &live {
+        words.remove(word);
+    }
+    let has = |op: i64| code.values().any(|instr| instr.code == op);
+    let reads = code.values().any(|instr| instr.params().iter().any(|param| !matches!(param, Param::Imm(_))));
+    let writes = code.values().any(|instr| instr.write().is_some());
+    let rb = has(9) || code.values().any(|instr| instr.params().iter().any(|param| matches!(param, Param::Rel(_))));
+
+    let mut out = String::new();
+    let mut emit = || -> Result<(), Error> {
+        writeln!(out, "// Generated from an Intcode program by intcode::transpile, don't edit.\n")?;
+        write!(out, "const MEM: [i64; {}] = [", mem.len())?;
+        for (i, n) in mem.iter().enumerate() {
+            if i % 16 == 0 {
+                write!(out, "\n   ")?;
+            }
+            write!(out, " {},", n)?;
+        }
+        writeln!(out, "\n];\n")?;
+        if writes {
+            write!(out, "const CODE: [usize; {}] = [", words.len())?;
+            for (i, n) in words.iter().enumerate() {
+                if i % 16 == 0 {
+                    write!(out, "\n   ")?;
+                }
+                write!(out, " {},", n)?;
+            }
+            writeln!(out, "\n];\n")?;
+        }
+
+        writeln!(out, "pub struct Machine {{")?;
+        writeln!(out, "    pub mem: Vec<i64>,")?;
+        writeln!(out, "    pc: usize,")?;
+        if rb {
+            writeln!(out, "    rb: i64,")?;
+        }
+        writeln!(out, "}}\n")?;
+        writeln!(out, "impl Machine {{")?;
+        writeln!(out, "    pub fn new() -> Self {{")?;
+        writeln!(out, "        Machine {{ mem: MEM.to_vec(), pc: 0{} }}", if rb { ", rb: 0" } else { "" })?;
+        writeln!(out, "    }}\n")?;
+        if reads {
+            writeln!(out, "    fn read(&self, i: i64) -> i64 {{")?;
+            writeln!(out, "        self.mem.get(i as usize).copied().unwrap_or(0)")?;
+            writeln!(out, "    }}\n")?;
+        }
+        if writes {
+            writeln!(out, "    fn write(&mut self, i: i64, val: i64) {{")?;
+            writeln!(out, "        let i = i as usize;")?;
+            writeln!(out, "        if CODE.binary_search(&i).is_ok() {{")?;
+            writeln!(out, "            panic!(\"instruction at {{}} writes to code at {{}}\", self.pc, i);")?;
+            writeln!(out, "        }}")?;
+            writeln!(out, "        if i >= self.mem.len() {{")?;
+            writeln!(out, "            self.mem.resize(i + 1, 0);")?;
+            writeln!(out, "        }}")?;
+            writeln!(out, "        self.mem[i] = val;")?;
+            writeln!(out, "    }}\n")?;
+        }
+        let input = if has(3) { "mut input" } else { "_input" };
+        let output = if has(4) { "mut output" } else { "_output" };
+        writeln!(out, "    pub fn run<I, O>(&mut self, {}: I, {}: O) -> bool", input, output)?;
+        writeln!(out, "        where I: FnMut() -> Option<i64>, O: FnMut(i64) {{")?;
+        writeln!(out, "        loop {{")?;
+        writeln!(out, "            match self.pc {{")?;
+        for instr in code.values() {
+            arm(&mut out, instr, &live)?;
+        }
+        writeln!(out, "                pc => panic!(\"jumped to {{}}, which isn't an instruction\", pc),")?;
+        writeln!(out, "            }}")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")
+    };
+    emit().expect("writing to a String can't fail");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::transpile;
+    use crate::intcode::Computer;
+    use std::iter::once;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::{env, fs};
+
+    /// Transpiles `mem` into a program that takes its inputs as arguments and prints its outputs, and
+    /// builds it with warnings as errors
+    fn build(dir: &Path, name: &str, mem: &[i64]) -> PathBuf {
+        let mut src = transpile(mem).unwrap();
+        src.push_str(r#"
+fn main() {
+    let mut input = std::env::args().skip(1).map(|n| n.parse().unwrap());
+    Machine::new().run(|| input.next(), |n| println!("{}", n));
+}
+"#);
+        let file = dir.join(name).with_extension("rs");
+        fs::write(&file, src).unwrap();
+        let status = Command::new("rustc")
+            .args(["-O", "--edition", "2018", "-D", "warnings", "-o"])
+            .arg(dir.join(name))
+            .arg(file)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} doesn't build", name);
+        dir.join(name)
+    }
+
+    fn run(exe: &Path, inputs: &[i64]) -> Vec<i64> {
+        let out = Command::new(exe).args(inputs.iter().map(|n| n.to_string())).output().unwrap();
+        String::from_utf8(out.stdout).unwrap()
+            .lines()
+            .map(|n| n.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_interpreter() {
+        let dir = env::temp_dir().join(format!("intcode-transpile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mem = Computer::load(include_str!("../../input/2019/day9.txt")).unwrap();
+        let exe = build(&dir, "day9", &mem);
+        for mode in [1, 2] {
+            let mut com = Computer::init(&mem, once(mode));
+            com.compute();
+            assert_eq!(run(&exe, &[mode]), com.recv_all().collect::<Vec<_>>());
+        }
+
+        // the robot's program starts with an always-taken jump over its data
+        let mem = Computer::load(include_str!("../../input/2019/day11.txt")).unwrap();
+        let exe = build(&dir, "day11", &mem);
+        let mut com = Computer::init(&mem, vec![0; 5]);
+        com.compute();
+        assert_eq!(run(&exe, &[0; 5]), com.recv_all().collect::<Vec<_>>());
+
+        let exe = build(&dir, "self", &[1105, 1, 0, 1106, 0, 3, 104, 5, 99]);
+        assert_eq!(run(&exe, &[]), vec![5]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}